@@ -0,0 +1,79 @@
+use chapa_chapa_wizard::levels::{BitGrid, Grid, Metric, WalkableTiles};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const SIZE: usize = 512;
+
+/// Mostly blocked map, so that searches scan a few rings before finding walkable tiles.
+fn sparse_walkable() -> Grid<bool> {
+    let mut grid = Grid::new(SIZE, SIZE, false);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            if (x * 7 + y * 13) % 37 == 0 {
+                grid.set(x, y, true).unwrap();
+            }
+        }
+    }
+    grid
+}
+
+fn is_walkable_local(c: &mut Criterion) {
+    let grid = sparse_walkable();
+    let bits = BitGrid::from(&grid);
+    let walkable = WalkableTiles::from(bits.clone());
+
+    let mut group = c.benchmark_group("is_walkable_local");
+    group.bench_function("walkable_tiles", |b| {
+        b.iter(|| {
+            (0..SIZE)
+                .flat_map(|y| (0..SIZE).map(move |x| (x, y)))
+                .filter(|&(x, y)| walkable.is_walkable_local(black_box(x), black_box(y)))
+                .count()
+        })
+    });
+    group.bench_function("bool_grid", |b| {
+        b.iter(|| grid.iter().filter(|(_, _, &walkable)| walkable).count())
+    });
+    group.bench_function("bit_grid", |b| {
+        b.iter(|| bits.iter().filter(|(_, _, &walkable)| walkable).count())
+    });
+    group.finish();
+}
+
+fn search(c: &mut Criterion) {
+    let grid = sparse_walkable();
+    let bits = BitGrid::from(&grid);
+    let walkable = WalkableTiles::from(bits.clone());
+    let from = (SIZE / 2 + 1, SIZE / 2);
+
+    let mut group = c.benchmark_group("search");
+    group.bench_function("nearest_walkable_tiles_local", |b| {
+        b.iter(|| walkable.nearest_walkable_tiles_local(black_box(from)))
+    });
+    group.bench_function("search_from_pos", |b| {
+        b.iter(|| bits.search_from_pos(black_box(from.0), black_box(from.1), |&w| w))
+    });
+    group.bench_function("nearest_bool_grid", |b| {
+        b.iter(|| {
+            grid.nearest(
+                black_box(from.0),
+                black_box(from.1),
+                Metric::Euclidean,
+                |&w| w,
+            )
+        })
+    });
+    group.bench_function("nearest_bit_grid", |b| {
+        b.iter(|| {
+            bits.nearest(
+                black_box(from.0),
+                black_box(from.1),
+                Metric::Euclidean,
+                |&w| w,
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, is_walkable_local, search);
+criterion_main!(benches);
@@ -0,0 +1,70 @@
+//! Checks levels without starting the game, exiting with 1 when any is misconfigured.
+//!
+//! `ccw-validate` checks every level of `assets/levels/levels.manifest.json`,
+//! `ccw-validate <map.tmx> <config.ccwl.json>...` checks the given pairs.
+
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use bevy::asset::AssetPlugin;
+use chapa_chapa_wizard::levels::{registry::LevelManifest, validation::validate_level_files};
+
+const MANIFEST: &str = "levels/levels.manifest.json";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let levels = if args.is_empty() {
+        // The game loads levels from bevy's default asset folder
+        match manifest_levels(Path::new(&AssetPlugin::default().asset_folder)) {
+            Ok(levels) => levels,
+            Err(message) => {
+                eprintln!("{message}");
+                return ExitCode::from(2);
+            }
+        }
+    } else if args.len() % 2 == 0 {
+        args.chunks(2)
+            .map(|pair| (PathBuf::from(&pair[0]), PathBuf::from(&pair[1])))
+            .collect()
+    } else {
+        eprintln!("Usage: ccw-validate [<map.tmx> <config.ccwl.json>]...");
+        return ExitCode::from(2);
+    };
+
+    let mut failed = 0;
+    for (map, config) in &levels {
+        let errors = validate_level_files(map, config);
+        if errors.is_empty() {
+            println!("ok    {} + {}", map.display(), config.display());
+            continue;
+        }
+        failed += 1;
+        println!("error {} + {}", map.display(), config.display());
+        for error in errors {
+            println!("      {error}");
+        }
+    }
+
+    println!("{} of {} levels misconfigured", failed, levels.len());
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Map and config paths of the manifest levels, which are relative to the asset folder.
+fn manifest_levels(assets: &Path) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let path = assets.join(MANIFEST);
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+    let manifest: LevelManifest = serde_json::from_str(&text)
+        .map_err(|e| format!("Could not parse {}: {e}", path.display()))?;
+    Ok(manifest
+        .levels
+        .into_iter()
+        .map(|level| (assets.join(level.map), assets.join(level.config)))
+        .collect())
+}
@@ -0,0 +1,502 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    helpers::coordinate_utils::{MapOrientation, TileLayout},
+    levels::{
+        elevation::{ElevationTiles, FloorRange},
+        pathfinding::TileCoord,
+        BitGrid,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColliderShape {
+    Aabb { half_extents: Vec2 },
+    Circle { radius: f32 },
+}
+
+/// Shape checked against non-walkable tiles, placed at the entity translation plus `offset`.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Collider {
+    pub shape: ColliderShape,
+    pub offset: Vec2,
+}
+
+impl Default for Collider {
+    fn default() -> Self {
+        Self::aabb(Vec2::ZERO)
+    }
+}
+
+impl Collider {
+    pub fn aabb(half_extents: Vec2) -> Self {
+        Self {
+            shape: ColliderShape::Aabb { half_extents },
+            offset: Vec2::ZERO,
+        }
+    }
+
+    pub fn circle(radius: f32) -> Self {
+        Self {
+            shape: ColliderShape::Circle { radius },
+            offset: Vec2::ZERO,
+        }
+    }
+
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn half_extents(&self) -> Vec2 {
+        match self.shape {
+            ColliderShape::Aabb { half_extents } => half_extents,
+            ColliderShape::Circle { radius } => Vec2::splat(radius),
+        }
+    }
+
+    fn overlaps_rect(&self, center: Vec2, rect_center: Vec2, rect_half_extents: Vec2) -> bool {
+        let delta = (center - rect_center).abs();
+        match self.shape {
+            ColliderShape::Aabb { half_extents } => {
+                let reach = half_extents + rect_half_extents;
+                delta.x < reach.x && delta.y < reach.y
+            }
+            ColliderShape::Circle { radius } => {
+                let closest = (delta - rect_half_extents).max(Vec2::ZERO);
+                closest.length_squared() < radius * radius
+            }
+        }
+    }
+}
+
+/// World space view of a walkability grid laid out the same way as the Tiled map.
+#[derive(Clone, Copy)]
+pub struct TileCollisionGrid<'a> {
+    walkable: &'a BitGrid,
+    layout: TileLayout,
+    elevation: Option<(&'a ElevationTiles, FloorRange)>,
+}
+
+impl<'a> TileCollisionGrid<'a> {
+    /// Orthogonal grid whose top left tile is centered on `top_left`.
+    pub fn new(walkable: &'a BitGrid, top_left: Vec2, tile_size: Vec2) -> Self {
+        let height = walkable.y_max() as f32;
+        let layout = TileLayout::new(
+            MapOrientation::Orthogonal,
+            tile_size,
+            UVec2::new(walkable.x_max() as u32, walkable.y_max() as u32),
+        )
+        .at(top_left - Vec2::new(0., (height - 1.) * tile_size.y));
+        Self::from_map(walkable, layout)
+    }
+
+    /// `layout` placed where the map is, see [`TileLayout::at`].
+    pub fn from_map(walkable: &'a BitGrid, layout: TileLayout) -> Self {
+        Self {
+            walkable,
+            layout,
+            elevation: None,
+        }
+    }
+
+    /// Additionally blocks tiles on floors not reachable from `floor` at `position`.
+    pub fn on_floor(mut self, elevation: &'a ElevationTiles, floor: u8, position: Vec2) -> Self {
+        let floors = self
+            .tile_at(position)
+            .map(|(x, y)| elevation.reachable_floors(floor, x, y))
+            .unwrap_or(FloorRange::single(floor));
+        self.elevation = Some((elevation, floors));
+        self
+    }
+
+    pub fn tile_at(&self, world_pos: Vec2) -> Option<(usize, usize)> {
+        let index = self.layout.tile_index(world_pos);
+        (index.x >= 0 && index.y >= 0).then_some((index.x as usize, index.y as usize))
+    }
+
+    /// Whether tile (x, y) can be walked on, from the floors of [`Self::on_floor`] if set.
+    pub fn is_walkable(&self, x: usize, y: usize) -> bool {
+        !self.is_blocked(IVec2::new(x as i32, y as i32))
+    }
+
+    /// Walkable tile whose center is the closest to `world_pos`, measured in world space.
+    pub fn nearest_walkable_tile(&self, world_pos: Vec2) -> Option<TileCoord> {
+        let mut nearest: Option<(TileCoord, f32)> = None;
+        self.walkable.for_each(|x, y, _| {
+            if !self.is_walkable(x, y) {
+                return;
+            }
+            let distance = self.layout.tile_to_world(x, y).distance_squared(world_pos);
+            if nearest.map_or(true, |(_, d)| distance < d) {
+                nearest = Some(((x, y), distance));
+            }
+        });
+        nearest.map(|(tile, _)| tile)
+    }
+
+    /// Tiles outside of the grid are treated as blocked.
+    fn is_blocked(&self, index: IVec2) -> bool {
+        if index.x < 0 || index.y < 0 {
+            return true;
+        }
+        let (x, y) = (index.x as usize, index.y as usize);
+        !self.walkable.get(x, y).copied().unwrap_or(false)
+            || self
+                .elevation
+                .map(|(elevation, floors)| !elevation.is_passable(x, y, &floors))
+                .unwrap_or(false)
+    }
+
+    pub fn collides(&self, collider: &Collider, position: Vec2) -> bool {
+        let center = position + collider.offset;
+        if self.layout.orientation != MapOrientation::Orthogonal {
+            return self
+                .sample_points(collider, center)
+                .any(|point| self.is_blocked(self.layout.tile_index(point)));
+        }
+        self.overlapped_tiles(collider, center).next().is_some()
+    }
+
+    /// How deep `collider` is in each blocked tile it overlaps.
+    fn tile_depths(&self, collider: &Collider, position: Vec2) -> HashMap<IVec2, f32> {
+        let center = position + collider.offset;
+        let tile_half_extents = self.layout.tile_size / 2.;
+        let mut depths = HashMap::default();
+        if self.layout.orientation != MapOrientation::Orthogonal {
+            // A tile is as deep as the deepest point inside it
+            let points = self
+                .sample_points(collider, center)
+                .map(|point| (point, self.layout.tile_index(point)))
+                .filter(|(_, index)| self.is_blocked(*index));
+            for (point, index) in points {
+                let offset = (point - self.layout.tile_center(index)).abs();
+                let depth = match self.layout.orientation {
+                    MapOrientation::Isometric => {
+                        let offset = offset / tile_half_extents;
+                        let inside: f32 = 1. - offset.x - offset.y;
+                        inside.max(0.) * tile_half_extents.min_element()
+                    }
+                    _ => (tile_half_extents - offset).max(Vec2::ZERO).min_element(),
+                };
+                let deepest: &mut f32 = depths.entry(index).or_default();
+                *deepest = deepest.max(depth);
+            }
+            return depths;
+        }
+
+        // Distance to push the collider out of each tile along its shallowest axis
+        let extents = collider.half_extents();
+        for index in self.overlapped_tiles(collider, center) {
+            let offset = (center - self.layout.tile_center(index)).abs();
+            depths.insert(index, (extents + tile_half_extents - offset).min_element());
+        }
+        depths
+    }
+
+    /// Blocked tiles of an orthogonal map overlapped by `collider` centered on `center`.
+    fn overlapped_tiles<'b>(
+        &'b self,
+        collider: &'b Collider,
+        center: Vec2,
+    ) -> impl Iterator<Item = IVec2> + 'b {
+        let extents = collider.half_extents();
+        let min = self
+            .layout
+            .tile_index(center + Vec2::new(-extents.x, extents.y));
+        let max = self
+            .layout
+            .tile_index(center + Vec2::new(extents.x, -extents.y));
+        let tile_half_extents = self.layout.tile_size / 2.;
+
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+            .filter(move |index| {
+                self.is_blocked(*index)
+                    && collider.overlaps_rect(
+                        center,
+                        self.layout.tile_center(*index),
+                        tile_half_extents,
+                    )
+            })
+    }
+
+    /// Points of the shape at most a quarter tile apart, to check diamond and hexagon cells.
+    fn sample_points<'b>(
+        &self,
+        collider: &'b Collider,
+        center: Vec2,
+    ) -> impl Iterator<Item = Vec2> + 'b {
+        let extents = collider.half_extents();
+        let spacing = self.layout.tile_size.min_element() / 4.;
+        let steps = (extents * 2. / spacing).ceil().max(Vec2::ONE).as_uvec2();
+
+        (0..=steps.y)
+            .flat_map(move |y| (0..=steps.x).map(move |x| UVec2::new(x, y)))
+            .map(move |step| center - extents + extents * 2. * step.as_vec2() / steps.as_vec2())
+            .filter(move |point| match collider.shape {
+                ColliderShape::Aabb { .. } => true,
+                ColliderShape::Circle { radius } => point.distance(center) <= radius,
+            })
+            .chain([center])
+    }
+
+    /// Whether `collider` may move from `from` to `to`. Colliders already overlapping blocked
+    /// tiles may move, as long as they don't get any deeper into them. Tiles they start
+    /// overlapping may be entered as deep as the deepest one they overlapped, so that they can
+    /// slide along a wall they're stuck in.
+    fn allows_move(&self, collider: &Collider, from: Vec2, to: Vec2) -> bool {
+        if !self.collides(collider, to) {
+            return true;
+        }
+        if !self.collides(collider, from) {
+            return false;
+        }
+        let before = self.tile_depths(collider, from);
+        let deepest = before.values().copied().fold(0., f32::max);
+        self.tile_depths(collider, to)
+            .into_iter()
+            .all(|(index, depth)| depth <= before.get(&index).copied().unwrap_or(deepest))
+    }
+
+    /// Moves `collider` from `position` by `delta` in sub-tile steps, sliding along blocked
+    /// tiles on the free axis. Returns the furthest reachable position.
+    pub fn resolve_movement(&self, collider: &Collider, position: Vec2, delta: Vec2) -> Vec2 {
+        if delta == Vec2::ZERO {
+            return position;
+        }
+
+        let max_step = (self.layout.tile_size.min_element() / 2.).max(f32::EPSILON);
+        let steps = (delta.length() / max_step).ceil().max(1.);
+        let step = delta / steps;
+
+        let mut current = position;
+        for _ in 0..steps as usize {
+            let candidates = [step, Vec2::new(step.x, 0.), Vec2::new(0., step.y)];
+            match candidates
+                .into_iter()
+                .filter(|c| *c != Vec2::ZERO)
+                .map(|c| current + c)
+                .find(|next| self.allows_move(collider, current, *next))
+            {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{UVec2, Vec2};
+
+    use crate::{
+        helpers::coordinate_utils::{MapOrientation, TileLayout},
+        levels::{
+            elevation::{ElevationTiles, TileElevation},
+            grid_from, BitGrid, Grid,
+        },
+    };
+
+    use super::{Collider, TileCollisionGrid};
+
+    const TILE: f32 = 64.;
+
+    fn center_of(x: usize, y: usize) -> Vec2 {
+        Vec2::new(x as f32 * TILE, -(y as f32) * TILE)
+    }
+
+    #[test]
+    fn test_point_collider_matches_tile_center_check() {
+        let grid = grid_from(&[
+            "..#", //
+            "...",
+        ]);
+        let tiles = TileCollisionGrid::new(&grid, Vec2::ZERO, Vec2::splat(TILE));
+        let point = Collider::default();
+        assert!(!tiles.collides(&point, center_of(1, 0)));
+        assert!(tiles.collides(&point, center_of(2, 0)));
+        assert!(tiles.collides(&point, center_of(3, 0)));
+    }
+
+    #[test]
+    fn test_aabb_overlaps_neighbour_tile() {
+        let grid = grid_from(&[
+            "..#", //
+            "...",
+        ]);
+        let tiles = TileCollisionGrid::new(&grid, Vec2::ZERO, Vec2::splat(TILE));
+        let body = Collider::aabb(Vec2::new(20., 20.));
+        assert!(!tiles.collides(&body, center_of(1, 0)));
+        assert!(tiles.collides(&body, center_of(1, 0) + Vec2::new(20., 0.)));
+    }
+
+    #[test]
+    fn test_circle_does_not_collide_with_tile_corner() {
+        let grid = grid_from(&[
+            "...", //
+            "..#", //
+        ]);
+        let tiles = TileCollisionGrid::new(&grid, Vec2::ZERO, Vec2::splat(TILE));
+        // Diagonally next to the blocked corner: the box hits it, the circle does not
+        let position = center_of(1, 0) + Vec2::new(20., -20.);
+        assert!(tiles.collides(&Collider::aabb(Vec2::splat(14.)), position));
+        assert!(!tiles.collides(&Collider::circle(14.), position));
+    }
+
+    #[test]
+    fn test_offset_moves_shape() {
+        let grid = grid_from(&[
+            "...", //
+            "#..",
+        ]);
+        let tiles = TileCollisionGrid::new(&grid, Vec2::ZERO, Vec2::splat(TILE));
+        let feet = Collider::aabb(Vec2::splat(8.)).with_offset(Vec2::new(0., -TILE));
+        assert!(tiles.collides(&feet, center_of(0, 0)));
+        assert!(!tiles.collides(&feet, center_of(1, 0)));
+    }
+
+    #[test]
+    fn test_slides_along_wall() {
+        let grid = grid_from(&[
+            "..#", //
+            "..#", //
+            "..#",
+        ]);
+        let tiles = TileCollisionGrid::new(&grid, Vec2::ZERO, Vec2::splat(TILE));
+        let body = Collider::aabb(Vec2::splat(16.));
+        let start = center_of(1, 0);
+        let end = tiles.resolve_movement(&body, start, Vec2::new(40., -40.));
+        assert!(end.x < start.x + 40.);
+        assert_eq!(start.y - 40., end.y);
+        assert!(!tiles.collides(&body, end));
+    }
+
+    #[test]
+    fn test_does_not_tunnel_through_thin_wall() {
+        let grid = grid_from(&[
+            ".#.", //
+        ]);
+        let tiles = TileCollisionGrid::new(&grid, Vec2::ZERO, Vec2::splat(TILE));
+        let body = Collider::aabb(Vec2::splat(8.));
+        let end = tiles.resolve_movement(&body, center_of(0, 0), Vec2::new(3. * TILE, 0.));
+        assert!(end.x < center_of(1, 0).x);
+    }
+
+    #[test]
+    fn test_stuck_collider_only_moves_out() {
+        let grid = grid_from(&[
+            "..##", //
+            "..##",
+        ]);
+        let tiles = TileCollisionGrid::new(&grid, Vec2::ZERO, Vec2::splat(TILE));
+        let body = Collider::aabb(Vec2::splat(16.));
+        // The right edge is a few pixels into the wall, the center is still walkable
+        let start = center_of(1, 0) + Vec2::new(20., -8.);
+        assert!(tiles.collides(&body, start));
+
+        assert_eq!(
+            start,
+            tiles.resolve_movement(&body, start, Vec2::new(2. * TILE, 0.))
+        );
+        // Only the part along the wall is left of a diagonal move into it
+        assert_eq!(
+            start - Vec2::new(0., TILE / 2.),
+            tiles.resolve_movement(&body, start, Vec2::new(TILE / 2., -TILE / 2.))
+        );
+        assert_eq!(
+            start - Vec2::new(20., 0.),
+            tiles.resolve_movement(&body, start, Vec2::new(-20., 0.))
+        );
+    }
+
+    #[test]
+    fn test_stuck_collider_slides_along_wall() {
+        let grid = grid_from(&[
+            "..#", //
+            "..#", //
+            "..#",
+        ]);
+        let tiles = TileCollisionGrid::new(&grid, Vec2::ZERO, Vec2::splat(TILE));
+        let body = Collider::aabb(Vec2::splat(16.));
+        // A few pixels into the wall, sliding down overlaps its next tile as well
+        let start = center_of(1, 0) + Vec2::new(20., 0.);
+        assert!(tiles.collides(&body, start));
+
+        assert_eq!(
+            start - Vec2::new(0., TILE),
+            tiles.resolve_movement(&body, start, Vec2::new(0., -TILE))
+        );
+        let end = tiles.resolve_movement(&body, start, Vec2::new(TILE, -TILE));
+        assert_eq!(start.x, end.x);
+        assert!(end.y < start.y - TILE / 2.);
+    }
+
+    #[test]
+    fn test_cliff_blocks_lower_floor() {
+        let grid = BitGrid::filled(1, 3, true);
+        let ground = TileElevation {
+            floor: 0,
+            connector: false,
+        };
+        let plateau = TileElevation { floor: 1, ..ground };
+        let elevation =
+            ElevationTiles::from(Grid::try_from(vec![vec![ground, ground, plateau]]).unwrap());
+        let body = Collider::aabb(Vec2::splat(8.));
+        let start = center_of(1, 0);
+
+        let tiles = TileCollisionGrid::new(&grid, Vec2::ZERO, Vec2::splat(TILE));
+        assert_eq!(
+            center_of(2, 0),
+            tiles.resolve_movement(&body, start, Vec2::new(TILE, 0.))
+        );
+
+        let tiles = tiles.on_floor(&elevation, 0, start);
+        assert!(tiles.resolve_movement(&body, start, Vec2::new(TILE, 0.)).x < center_of(2, 0).x);
+    }
+
+    #[test]
+    fn test_nearest_walkable_tile_stays_on_floor() {
+        let grid = BitGrid::filled(1, 4, true);
+        let ground = TileElevation {
+            floor: 0,
+            connector: false,
+        };
+        let plateau = TileElevation { floor: 1, ..ground };
+        let elevation = ElevationTiles::from(
+            Grid::try_from(vec![vec![ground, plateau, plateau, ground]]).unwrap(),
+        );
+        let tiles = TileCollisionGrid::new(&grid, Vec2::ZERO, Vec2::splat(TILE));
+        let position = center_of(1, 0) + Vec2::new(TILE / 4., 0.);
+
+        assert_eq!(Some((1, 0)), tiles.nearest_walkable_tile(position));
+        let tiles = tiles.on_floor(&elevation, 0, position);
+        assert!(!tiles.is_walkable(1, 0));
+        assert_eq!(Some((0, 0)), tiles.nearest_walkable_tile(position));
+    }
+
+    #[test]
+    fn test_isometric_blocks_diamond_cells() {
+        let grid = grid_from(&[
+            "..", //
+            ".#",
+        ]);
+        let layout = TileLayout::new(
+            MapOrientation::Isometric,
+            Vec2::new(TILE, TILE / 2.),
+            UVec2::new(2, 2),
+        );
+        let tiles = TileCollisionGrid::from_map(&grid, layout);
+        let body = Collider::aabb(Vec2::splat(4.));
+
+        // (0, 1) and (1, 0) sit left and right of the blocked bottom tile
+        assert!(tiles.collides(&body, layout.tile_to_world(1, 1)));
+        assert!(!tiles.collides(&body, layout.tile_to_world(0, 1)));
+        assert!(!tiles.collides(&body, layout.tile_to_world(1, 0)));
+        // Walking straight down from the top tile stops short of the blocked one
+        let start = layout.tile_to_world(0, 0);
+        let end = tiles.resolve_movement(&body, start, Vec2::new(0., -TILE / 2.));
+        assert!(end.y > layout.tile_to_world(1, 1).y + TILE / 8.);
+    }
+}
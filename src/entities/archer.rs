@@ -1,14 +1,28 @@
 use bevy::asset::AssetServer;
+use bevy::prelude::*;
 use bevy::sprite::TextureAtlas;
-use bevy::{ecs::system::Resource, prelude::Handle, asset::Assets};
+use bevy::{asset::Assets, ecs::system::Resource, prelude::Handle};
 use bevy_asset_loader::prelude::*;
-use bevy::prelude::*;
 
 use crate::animation::AnimationBundle;
 
+use super::troop_prefab;
+
+/// From the center of archer sprites to their feet.
+pub const ARCHER_FOOT_OFFSET: f32 = -28.;
+
 #[derive(AssetCollection, Resource)]
 pub struct ArcherBlue {
-    #[asset(texture_atlas(tile_size_x = 192., tile_size_y = 192., columns = 8, rows = 7, padding_x = 0., padding_y = 0., offset_x = 0., offset_y = 0.))]
+    #[asset(texture_atlas(
+        tile_size_x = 192.,
+        tile_size_y = 192.,
+        columns = 8,
+        rows = 7,
+        padding_x = 0.,
+        padding_y = 0.,
+        offset_x = 0.,
+        offset_y = 0.
+    ))]
     #[asset(path = "sprites/Factions/Knights/Troops/Archer/Blue/Archer_Blue.png")]
     pub texture_atlas: Handle<TextureAtlas>,
     #[asset(path = "sprites/Factions/Knights/Troops/Archer/Blue/Archer_Blue.animations.json")]
@@ -17,9 +31,34 @@ pub struct ArcherBlue {
 
 #[derive(AssetCollection, Resource)]
 pub struct ArcherRed {
-    #[asset(texture_atlas(tile_size_x = 192., tile_size_y = 192., columns = 8, rows = 7, padding_x = 0., padding_y = 0., offset_x = 0., offset_y = 0.))]
+    #[asset(texture_atlas(
+        tile_size_x = 192.,
+        tile_size_y = 192.,
+        columns = 8,
+        rows = 7,
+        padding_x = 0.,
+        padding_y = 0.,
+        offset_x = 0.,
+        offset_y = 0.
+    ))]
     #[asset(path = "sprites/Factions/Knights/Troops/Archer/Red/Archer_Red.png")]
     pub texture_atlas: Handle<TextureAtlas>,
     #[asset(path = "sprites/Factions/Knights/Troops/Archer/Red/Archer_Red.animations.json")]
     pub animations: Handle<AnimationBundle>,
 }
+
+pub fn archer_blue_prefab(world: &mut World, entity: Entity) -> bool {
+    let Some(archer) = world.get_resource::<ArcherBlue>() else {
+        return false;
+    };
+    let (texture_atlas, animations) = (archer.texture_atlas.clone(), archer.animations.clone());
+    troop_prefab(world, entity, texture_atlas, animations, ARCHER_FOOT_OFFSET)
+}
+
+pub fn archer_red_prefab(world: &mut World, entity: Entity) -> bool {
+    let Some(archer) = world.get_resource::<ArcherRed>() else {
+        return false;
+    };
+    let (texture_atlas, animations) = (archer.texture_atlas.clone(), archer.animations.clone());
+    troop_prefab(world, entity, texture_atlas, animations, ARCHER_FOOT_OFFSET)
+}
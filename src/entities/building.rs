@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use bevy_asset_loader::prelude::*;
+
+use crate::helpers::y_sort::YSort;
+
+#[derive(AssetCollection, Resource)]
+pub struct Buildings {
+    #[asset(path = "sprites/Factions/Knights/Buildings/House/House_Blue.png")]
+    pub house: Handle<Image>,
+    #[asset(path = "sprites/Factions/Knights/Buildings/Tower/Tower_Blue.png")]
+    pub tower: Handle<Image>,
+    #[asset(path = "sprites/Factions/Knights/Buildings/Castle/Castle_Blue.png")]
+    pub castle: Handle<Image>,
+    #[asset(path = "sprites/Factions/Goblins/Buildings/Wood_House/Goblin_House.png")]
+    pub goblin_house: Handle<Image>,
+}
+
+pub fn house_prefab(world: &mut World, entity: Entity) -> bool {
+    building_prefab(world, entity, |b| b.house.clone())
+}
+
+pub fn tower_prefab(world: &mut World, entity: Entity) -> bool {
+    building_prefab(world, entity, |b| b.tower.clone())
+}
+
+pub fn castle_prefab(world: &mut World, entity: Entity) -> bool {
+    building_prefab(world, entity, |b| b.castle.clone())
+}
+
+pub fn goblin_house_prefab(world: &mut World, entity: Entity) -> bool {
+    building_prefab(world, entity, |b| b.goblin_house.clone())
+}
+
+/// Buildings stand on the bottom edge of their sprite, so they're sorted by it. False until the
+/// image is loaded, since its size is needed for that.
+fn building_prefab(
+    world: &mut World,
+    entity: Entity,
+    texture: impl FnOnce(&Buildings) -> Handle<Image>,
+) -> bool {
+    let Some(buildings) = world.get_resource::<Buildings>() else {
+        return false;
+    };
+    let texture = texture(buildings);
+    let Some(height) = world
+        .resource::<Assets<Image>>()
+        .get(&texture)
+        .map(|image| image.size().y)
+    else {
+        return false;
+    };
+    let transform = world.get::<Transform>(entity).copied().unwrap_or_default();
+
+    world.entity_mut(entity).insert((
+        SpriteBundle {
+            texture,
+            transform,
+            ..default()
+        },
+        YSort::new(-height / 2.),
+    ));
+    true
+}
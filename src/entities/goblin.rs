@@ -0,0 +1,61 @@
+use bevy::prelude::*;
+use bevy_asset_loader::prelude::*;
+
+use crate::animation::AnimationBundle;
+
+use super::troop_prefab;
+
+/// From the center of torch goblin sprites to their feet.
+pub const GOBLIN_FOOT_OFFSET: f32 = -28.;
+
+#[derive(AssetCollection, Resource)]
+pub struct TorchGoblinBlue {
+    #[asset(texture_atlas(
+        tile_size_x = 192.,
+        tile_size_y = 192.,
+        columns = 7,
+        rows = 5,
+        padding_x = 0.,
+        padding_y = 0.,
+        offset_x = 0.,
+        offset_y = 0.
+    ))]
+    #[asset(path = "sprites/Factions/Goblins/Troops/Torch/Blue/Torch_Blue.png")]
+    pub texture_atlas: Handle<TextureAtlas>,
+    #[asset(path = "sprites/Factions/Goblins/Troops/Torch/Blue/Torch_Blue.animations.json")]
+    pub animations: Handle<AnimationBundle>,
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct TorchGoblinRed {
+    #[asset(texture_atlas(
+        tile_size_x = 192.,
+        tile_size_y = 192.,
+        columns = 7,
+        rows = 5,
+        padding_x = 0.,
+        padding_y = 0.,
+        offset_x = 0.,
+        offset_y = 0.
+    ))]
+    #[asset(path = "sprites/Factions/Goblins/Troops/Torch/Red/Torch_Red.png")]
+    pub texture_atlas: Handle<TextureAtlas>,
+    #[asset(path = "sprites/Factions/Goblins/Troops/Torch/Red/Torch_Red.animations.json")]
+    pub animations: Handle<AnimationBundle>,
+}
+
+pub fn goblin_blue_prefab(world: &mut World, entity: Entity) -> bool {
+    let Some(goblin) = world.get_resource::<TorchGoblinBlue>() else {
+        return false;
+    };
+    let (texture_atlas, animations) = (goblin.texture_atlas.clone(), goblin.animations.clone());
+    troop_prefab(world, entity, texture_atlas, animations, GOBLIN_FOOT_OFFSET)
+}
+
+pub fn goblin_red_prefab(world: &mut World, entity: Entity) -> bool {
+    let Some(goblin) = world.get_resource::<TorchGoblinRed>() else {
+        return false;
+    };
+    let (texture_atlas, animations) = (goblin.texture_atlas.clone(), goblin.animations.clone());
+    troop_prefab(world, entity, texture_atlas, animations, GOBLIN_FOOT_OFFSET)
+}
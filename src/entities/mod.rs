@@ -1 +1,41 @@
 pub mod archer;
+pub mod building;
+pub mod goblin;
+
+use bevy::prelude::*;
+
+use crate::animation::{AnimationBundle, AnimationTimer};
+use crate::helpers::y_sort::YSort;
+use crate::levels::elevation::Floor;
+
+/// Animated sprite sheet with its feet `foot_offset` below its center, on the floor it's
+/// placed on. False until the animations are loaded.
+fn troop_prefab(
+    world: &mut World,
+    entity: Entity,
+    texture_atlas: Handle<TextureAtlas>,
+    animations: Handle<AnimationBundle>,
+    foot_offset: f32,
+) -> bool {
+    let Some(animations) = world
+        .resource::<Assets<AnimationBundle>>()
+        .get(&animations)
+        .cloned()
+    else {
+        return false;
+    };
+    let transform = world.get::<Transform>(entity).copied().unwrap_or_default();
+
+    world.entity_mut(entity).insert((
+        SpriteSheetBundle {
+            texture_atlas,
+            transform,
+            ..default()
+        },
+        animations,
+        AnimationTimer::default(),
+        YSort::new(foot_offset),
+        Floor::default(),
+    ));
+    true
+}
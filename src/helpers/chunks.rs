@@ -0,0 +1,265 @@
+use bevy::{math::Vec3Swizzles, prelude::*, utils::HashMap};
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::levels::Level;
+
+use super::{
+    coordinate_utils::TileLayout,
+    tiled::{spawn_layer_tilemaps, tilemap_z, LayerRendering, MapBounds, TiledMap},
+};
+
+/// Width and height of the chunks infinite Tiled maps are stored in.
+pub const CHUNK_SIZE: u32 = tiled::ChunkData::WIDTH;
+
+/// Chunks up to this many chunks away from the camera are spawned.
+const LOAD_RADIUS: i32 = 2;
+/// Spawned chunks further away than this are despawned. Bigger than [`LOAD_RADIUS`]
+/// so moving along a chunk border doesn't respawn chunks every frame.
+const UNLOAD_RADIUS: i32 = 3;
+
+/// Tile layer of an infinite map. Its [`LayerTilemap`](super::tiled::LayerTilemap)s are spawned
+/// per chunk around the camera.
+#[derive(Component)]
+pub struct InfiniteLayer {
+    map: Handle<TiledMap>,
+    layer_index: usize,
+    chunks: HashMap<IVec2, Vec<Entity>>,
+}
+
+impl InfiniteLayer {
+    pub fn new(map: Handle<TiledMap>, layer_index: usize) -> Self {
+        Self {
+            map,
+            layer_index,
+            chunks: HashMap::default(),
+        }
+    }
+}
+
+fn chunk_of(tmx: IVec2) -> IVec2 {
+    IVec2::new(
+        tmx.x.div_euclid(CHUNK_SIZE as i32),
+        tmx.y.div_euclid(CHUNK_SIZE as i32),
+    )
+}
+
+pub fn stream_chunks(
+    mut commands: Commands,
+    maps: Res<Assets<TiledMap>>,
+    camera: Query<&GlobalTransform, With<Camera>>,
+    mut layers: Query<(
+        Entity,
+        &mut InfiniteLayer,
+        &TileLayout,
+        &LayerRendering,
+        &GlobalTransform,
+    )>,
+) {
+    let Some(camera_translation) = camera.iter().next().map(|t| t.translation()) else {
+        return;
+    };
+
+    for (layer_entity, mut layer, layout, rendering, layer_transform) in layers.iter_mut() {
+        let Some(tiled_map) = maps.get(&layer.map) else {
+            continue;
+        };
+        let Some(tiled_layer) = tiled_map.map.get_layer(layer.layer_index) else {
+            continue;
+        };
+        let tiled::LayerType::Tiles(tiled::TileLayer::Infinite(layer_data)) =
+            tiled_layer.layer_type()
+        else {
+            continue;
+        };
+
+        let local = layer_transform
+            .affine()
+            .inverse()
+            .transform_point3(camera_translation)
+            .xy();
+        let center = camera_chunk(layout, &tiled_map.bounds, local);
+
+        layer.chunks.retain(|chunk_pos, tilemaps| {
+            let keep = (*chunk_pos - center).abs().max_element() <= UNLOAD_RADIUS;
+            if !keep {
+                for tilemap in tilemaps.iter() {
+                    commands.entity(*tilemap).despawn_recursive();
+                }
+            }
+            keep
+        });
+
+        for y in -LOAD_RADIUS..=LOAD_RADIUS {
+            for x in -LOAD_RADIUS..=LOAD_RADIUS {
+                let chunk_pos = center + IVec2::new(x, y);
+                if layer.chunks.contains_key(&chunk_pos) {
+                    continue;
+                }
+                let Some(chunk) = layer_data.get_chunk(chunk_pos.x, chunk_pos.y) else {
+                    continue;
+                };
+
+                let origin = chunk_origin(&tiled_map.bounds, chunk_pos)
+                    .center_in_world(&layout.grid_size(), &layout.tilemap_type());
+                let texture_count = tiled_map.tilemap_textures.len();
+                let tilemaps: Vec<Entity> = tiled_map
+                    .tilemap_textures
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(texture_index, tileset_texture)| {
+                        spawn_layer_tilemaps(
+                            &mut commands,
+                            tileset_texture,
+                            TilemapSize {
+                                x: CHUNK_SIZE,
+                                y: CHUNK_SIZE,
+                            },
+                            layout,
+                            origin.extend(tilemap_z(texture_index, texture_count)),
+                            rendering,
+                            // Chunk rows go down like TMX ones
+                            |tile_pos| {
+                                chunk.get_tile(
+                                    tile_pos.x as i32,
+                                    (CHUNK_SIZE - 1 - tile_pos.y) as i32,
+                                )
+                            },
+                        )
+                    })
+                    .collect();
+                commands.entity(layer_entity).push_children(&tilemaps);
+                layer.chunks.insert(chunk_pos, tilemaps);
+            }
+        }
+    }
+}
+
+/// Loads the walkability of the chunks around the camera into the level, as far as
+/// [`stream_chunks`] spawns their tiles, and unloads it along with them.
+pub fn stream_walkable_chunks(
+    maps: Res<Assets<TiledMap>>,
+    camera: Query<&GlobalTransform, With<Camera>>,
+    layers: Query<(&InfiniteLayer, &TileLayout, &GlobalTransform)>,
+    mut levels: Query<(&mut Level, &Handle<TiledMap>)>,
+) {
+    let Some(camera_translation) = camera.iter().next().map(|t| t.translation()) else {
+        return;
+    };
+
+    for (mut level, map_handle) in levels.iter_mut() {
+        let Some(tiled_map) = maps.get(map_handle) else {
+            continue;
+        };
+        // Every layer shares the map grid, so the first one is enough
+        let Some((_, layout, layer_transform)) =
+            layers.iter().find(|(layer, _, _)| layer.map == *map_handle)
+        else {
+            continue;
+        };
+        let local = layer_transform
+            .affine()
+            .inverse()
+            .transform_point3(camera_translation)
+            .xy();
+        let center = camera_chunk(layout, &tiled_map.bounds, local);
+        let bounds = tiled_map.bounds;
+
+        // Streaming doesn't change the level itself, validating it again would only repeat errors
+        let level = level.bypass_change_detection();
+        let far: Vec<_> = level
+            .loaded_chunks()
+            .copied()
+            .filter(|&(x, y)| {
+                let chunk_pos = chunk_of(bounds.min + IVec2::new(x as i32, y as i32));
+                (chunk_pos - center).abs().max_element() > UNLOAD_RADIUS
+            })
+            .collect();
+        for origin in far {
+            level.unload_chunk(origin);
+        }
+
+        for y in -LOAD_RADIUS..=LOAD_RADIUS {
+            for x in -LOAD_RADIUS..=LOAD_RADIUS {
+                let origin = (center + IVec2::new(x, y)) * CHUNK_SIZE as i32 - bounds.min;
+                if origin.cmpge(IVec2::ZERO).all() && origin.cmplt(bounds.size.as_ivec2()).all() {
+                    level.load_chunk((origin.x as usize, origin.y as usize));
+                }
+            }
+        }
+    }
+}
+
+/// Chunk under the camera at `local`, relative to the layer. Off the map, the chunk of the map
+/// closest to it.
+fn camera_chunk(layout: &TileLayout, bounds: &MapBounds, local: Vec2) -> IVec2 {
+    let camera_tile = layout
+        .tile_index(local)
+        .min(layout.size.as_ivec2() - IVec2::ONE)
+        .max(IVec2::ZERO);
+    chunk_of(bounds.min + camera_tile)
+}
+
+/// Bottom left tile of the chunk within the layer.
+fn chunk_origin(bounds: &MapBounds, chunk_pos: IVec2) -> TilePos {
+    TilePos {
+        x: (chunk_pos.x * CHUNK_SIZE as i32 - bounds.min.x) as u32,
+        y: (bounds.min.y + bounds.size.y as i32 - (chunk_pos.y + 1) * CHUNK_SIZE as i32) as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, UVec2, Vec2};
+    use bevy_ecs_tilemap::tiles::TilePos;
+
+    use crate::helpers::{
+        coordinate_utils::{MapOrientation, TileLayout},
+        tiled::MapBounds,
+    };
+
+    use super::{camera_chunk, chunk_of, chunk_origin};
+
+    #[test]
+    fn test_negative_tiles_belong_to_negative_chunks() {
+        assert_eq!(IVec2::new(0, 0), chunk_of(IVec2::new(0, 15)));
+        assert_eq!(IVec2::new(-1, 1), chunk_of(IVec2::new(-1, 16)));
+        assert_eq!(IVec2::new(-2, -1), chunk_of(IVec2::new(-17, -16)));
+    }
+
+    #[test]
+    fn test_chunk_origin_counts_rows_from_the_bottom() {
+        let bounds = MapBounds {
+            min: IVec2::new(-16, 0),
+            size: UVec2::new(32, 32),
+        };
+        assert_eq!(
+            TilePos { x: 0, y: 16 },
+            chunk_origin(&bounds, IVec2::new(-1, 0))
+        );
+        assert_eq!(
+            TilePos { x: 16, y: 0 },
+            chunk_origin(&bounds, IVec2::new(0, 1))
+        );
+    }
+
+    #[test]
+    fn test_camera_off_the_map_streams_the_closest_chunks() {
+        let bounds = MapBounds {
+            min: IVec2::new(-16, 0),
+            size: UVec2::new(32, 32),
+        };
+        let layout = TileLayout::new(MapOrientation::Orthogonal, Vec2::ONE, bounds.size);
+        assert_eq!(
+            IVec2::new(-1, 1),
+            camera_chunk(&layout, &bounds, Vec2::ZERO)
+        );
+        assert_eq!(
+            IVec2::new(-1, 1),
+            camera_chunk(&layout, &bounds, Vec2::new(-100., 0.))
+        );
+        assert_eq!(
+            IVec2::new(0, 0),
+            camera_chunk(&layout, &bounds, Vec2::new(100., 100.))
+        );
+    }
+}
@@ -1,5 +1,8 @@
-use bevy::math::Vec2;
-use bevy_ecs_tilemap::map::{TilemapGridSize, TilemapSize};
+use bevy::{
+    math::{IVec2, UVec2, Vec2, Vec2Swizzles},
+    prelude::Component,
+};
+use bevy_ecs_tilemap::map::{HexCoordSystem, IsoCoordSystem, TilemapGridSize, TilemapType};
 
 pub trait CoordinateOps {
     fn relative_to(&self, zero: &Self) -> Self;
@@ -9,8 +12,6 @@ pub trait CoordinateOps {
     fn abs(&self) -> Self;
 
     fn copy_signs(&self, other: &Self) -> Self;
-
-    fn tiled_top_left(&self, map_size: &TilemapSize, grid_size: &TilemapGridSize) -> Self;
 }
 
 impl CoordinateOps for Vec2 {
@@ -29,18 +30,306 @@ impl CoordinateOps for Vec2 {
     fn copy_signs(&self, other: &Self) -> Self {
         Vec2::new(self.x.copysign(other.x), self.y.copysign(other.y))
     }
+}
+
+/// Axis along which every other row (`Y`) or column (`X`) of staggered and hexagonal maps is
+/// shifted by half a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaggerAxis {
+    X,
+    Y,
+}
+
+/// Whether the odd or the even rows/columns are the shifted ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaggerIndex {
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MapOrientation {
+    #[default]
+    Orthogonal,
+    Isometric,
+    /// Staggered isometric, laid out like a hexagonal map with diamond cells.
+    Staggered {
+        axis: StaggerAxis,
+        index: StaggerIndex,
+    },
+    Hexagonal {
+        axis: StaggerAxis,
+        index: StaggerIndex,
+        /// Length of the flat sides, along the stagger axis
+        side_length: u32,
+    },
+}
+
+/// Cell layout of a tile layer as Tiled draws it, for every orientation. Converts world positions
+/// from and to the tile coordinates of the walkability grid, which are TMX style: the top left
+/// tile of the layer is (0, 0) and y goes down.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub struct TileLayout {
+    pub orientation: MapOrientation,
+    /// Width and height of the map tiles, not of the tileset images
+    pub tile_size: Vec2,
+    pub size: UVec2,
+    /// World position of the center of the bottom left tile, where bevy_ecs_tilemap puts
+    /// `TilePos` (0, 0). Zero for positions relative to the layer.
+    pub translation: Vec2,
+}
+
+impl TileLayout {
+    pub fn new(orientation: MapOrientation, tile_size: Vec2, size: UVec2) -> Self {
+        Self {
+            orientation,
+            tile_size,
+            size,
+            translation: Vec2::ZERO,
+        }
+    }
+
+    /// The same layout for a layer placed at `translation`.
+    pub fn at(mut self, translation: Vec2) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    pub fn contains(&self, tile: IVec2) -> bool {
+        tile.x >= 0 && tile.y >= 0 && tile.x < self.size.x as i32 && tile.y < self.size.y as i32
+    }
+
+    /// Tile under `world_pos`, possibly outside of the layer.
+    pub fn tile_index(&self, world_pos: Vec2) -> IVec2 {
+        self.pixel_to_tile(self.world_to_pixel(world_pos))
+    }
+
+    /// World position of the center of `tile`, which may be outside of the layer.
+    pub fn tile_center(&self, tile: IVec2) -> Vec2 {
+        self.pixel_to_world(self.pixel_center(tile))
+    }
+
+    /// Tile under `world_pos`, `None` outside of the layer.
+    pub fn tile_at(&self, world_pos: Vec2) -> Option<(usize, usize)> {
+        let tile = self.tile_index(world_pos);
+        self.contains(tile)
+            .then_some((tile.x as usize, tile.y as usize))
+    }
 
-    fn tiled_top_left(&self, map_size: &TilemapSize, grid_size: &TilemapGridSize) -> Self {
-        Vec2::new(self.x, self.y + grid_size.y * ((map_size.y - 1) as f32))
+    /// World position of the center of tile (x, y). Inverse of [`TileLayout::tile_at`].
+    pub fn tile_to_world(&self, x: usize, y: usize) -> Vec2 {
+        self.tile_center(IVec2::new(x as i32, y as i32))
+    }
+
+    /// World position of a Tiled object, whose coordinates are in pixels from the top left corner
+    /// of TMX tile (0, 0). `min` is the TMX tile at the top left of the layer. Isometric maps
+    /// express object positions along the tile axes, in tile height units.
+    pub fn object_to_world(&self, object_pos: Vec2, min: IVec2) -> Vec2 {
+        let pixel = match self.orientation {
+            MapOrientation::Isometric => {
+                let tile = object_pos / self.tile_size.y;
+                Vec2::new(
+                    (tile.x - tile.y + self.size.y as f32) * self.tile_size.x / 2.,
+                    (tile.x + tile.y) * self.tile_size.y / 2.,
+                )
+            }
+            _ => object_pos,
+        };
+        self.pixel_to_world(pixel - (self.pixel_center(min) - self.pixel_center(IVec2::ZERO)))
+    }
+
+    /// Grid size bevy_ecs_tilemap renders this layout with. Hexagonal cells are spaced by 3/4
+    /// of the grid size along the stagger axis, which is scaled to match Tiled's spacing.
+    pub fn grid_size(&self) -> TilemapGridSize {
+        let mut grid = TilemapGridSize {
+            x: self.tile_size.x,
+            y: self.tile_size.y,
+        };
+        let (axis, side) = match self.orientation {
+            MapOrientation::Staggered { axis, .. } => (axis, 0.),
+            MapOrientation::Hexagonal {
+                axis, side_length, ..
+            } => (axis, side_length as f32),
+            _ => return grid,
+        };
+        match axis {
+            StaggerAxis::X => grid.x = 2. * (grid.x + side) / 3.,
+            StaggerAxis::Y => grid.y = 2. * (grid.y + side) / 3.,
+        }
+        grid
+    }
+
+    /// Map type bevy_ecs_tilemap renders this layout with. Staggered isometric maps have the same
+    /// cell centers as hexagonal ones. bevy_ecs_tilemap rows go up, flipping the parity of the
+    /// shifted rows on maps with an even height.
+    pub fn tilemap_type(&self) -> TilemapType {
+        let (axis, index) = match self.orientation {
+            MapOrientation::Orthogonal => return TilemapType::Square,
+            MapOrientation::Isometric => return TilemapType::Isometric(IsoCoordSystem::Diamond),
+            MapOrientation::Staggered { axis, index }
+            | MapOrientation::Hexagonal { axis, index, .. } => (axis, index),
+        };
+        let shifted_parity = match index {
+            StaggerIndex::Odd => 1,
+            StaggerIndex::Even => 0,
+        };
+        TilemapType::Hexagon(match axis {
+            StaggerAxis::Y => {
+                if (self.size.y as i32 - 1 - shifted_parity).rem_euclid(2) == 1 {
+                    HexCoordSystem::RowOdd
+                } else {
+                    HexCoordSystem::RowEven
+                }
+            }
+            // Tiled shifts columns down, bevy_ecs_tilemap up
+            StaggerAxis::X => {
+                if shifted_parity == 1 {
+                    HexCoordSystem::ColumnEven
+                } else {
+                    HexCoordSystem::ColumnOdd
+                }
+            }
+        })
+    }
+
+    // Pixel space is the one Tiled draws the layer in: the origin is the top left corner of the
+    // layer bounding box and y goes down.
+
+    fn world_to_pixel(&self, world_pos: Vec2) -> Vec2 {
+        let relative = world_pos.relative_to(&self.translation);
+        Vec2::new(relative.x, -relative.y).undo_relative(&self.bottom_left_center())
+    }
+
+    fn pixel_to_world(&self, pixel: Vec2) -> Vec2 {
+        let relative = pixel.relative_to(&self.bottom_left_center());
+        Vec2::new(relative.x, -relative.y).undo_relative(&self.translation)
+    }
+
+    fn bottom_left_center(&self) -> Vec2 {
+        self.pixel_center(IVec2::new(0, self.size.y as i32 - 1))
+    }
+
+    fn pixel_center(&self, tile: IVec2) -> Vec2 {
+        let (w, h) = (self.tile_size.x, self.tile_size.y);
+        match self.orientation {
+            MapOrientation::Orthogonal => (tile.as_vec2() + 0.5) * self.tile_size,
+            MapOrientation::Isometric => Vec2::new(
+                (tile.x - tile.y + self.size.y as i32) as f32 * w / 2.,
+                (tile.x + tile.y + 1) as f32 * h / 2.,
+            ),
+            MapOrientation::Staggered { axis, index } => {
+                Stagger::new(axis, index, 0, self.tile_size).center(tile)
+            }
+            MapOrientation::Hexagonal {
+                axis,
+                index,
+                side_length,
+            } => Stagger::new(axis, index, side_length, self.tile_size).center(tile),
+        }
+    }
+
+    fn pixel_to_tile(&self, pixel: Vec2) -> IVec2 {
+        let (w, h) = (self.tile_size.x, self.tile_size.y);
+        match self.orientation {
+            MapOrientation::Orthogonal => (pixel / self.tile_size).floor().as_ivec2(),
+            MapOrientation::Isometric => {
+                let x = pixel.x / w - self.size.y as f32 / 2.;
+                let y = pixel.y / h;
+                IVec2::new((y + x).floor() as i32, (y - x).floor() as i32)
+            }
+            MapOrientation::Staggered { axis, index } => {
+                Stagger::new(axis, index, 0, self.tile_size).tile_at(pixel)
+            }
+            MapOrientation::Hexagonal {
+                axis,
+                index,
+                side_length,
+            } => Stagger::new(axis, index, side_length, self.tile_size).tile_at(pixel),
+        }
     }
 }
 
+/// Staggered and hexagonal pixel geometry, computed as if rows were staggered and transposed
+/// when columns are.
+struct Stagger {
+    transposed: bool,
+    shifted_parity: i32,
+    side: f32,
+    cell: Vec2,
+}
+
+impl Stagger {
+    fn new(axis: StaggerAxis, index: StaggerIndex, side_length: u32, tile_size: Vec2) -> Self {
+        let transposed = axis == StaggerAxis::X;
+        Self {
+            transposed,
+            shifted_parity: match index {
+                StaggerIndex::Odd => 1,
+                StaggerIndex::Even => 0,
+            },
+            side: side_length as f32,
+            cell: if transposed {
+                tile_size.yx()
+            } else {
+                tile_size
+            },
+        }
+    }
+
+    fn transpose<T: Vec2Swizzles>(&self, v: T) -> T {
+        if self.transposed {
+            v.yx()
+        } else {
+            v
+        }
+    }
+
+    fn center(&self, tile: IVec2) -> Vec2 {
+        self.transpose(self.row_center(self.transpose(tile)))
+    }
+
+    /// Cells are hexagons with flat sides of length `side` on the left and right, or diamonds.
+    fn tile_at(&self, pixel: Vec2) -> IVec2 {
+        let pixel = self.transpose(pixel);
+        let row_height = (self.cell.y + self.side) / 2.;
+        let row = ((pixel.y - self.cell.y / 2.) / row_height).round() as i32;
+        let column = ((pixel.x - self.cell.x / 2.) / self.cell.x).round() as i32;
+
+        // Scaled distance to the cell center, at most 1 inside the cell
+        let half = self.cell / 2.;
+        let gauge = |tile: IVec2| {
+            let d = (pixel - self.row_center(tile)).abs();
+            let slant = (half.y - self.side / 2.) * d.x / half.x;
+            (d.x / half.x).max((d.y + slant) / half.y)
+        };
+        let nearest = (row - 1..=row + 1)
+            .flat_map(|y| (column - 1..=column + 1).map(move |x| IVec2::new(x, y)))
+            .min_by(|a, b| gauge(*a).total_cmp(&gauge(*b)))
+            .unwrap_or(IVec2::new(column, row));
+        self.transpose(nearest)
+    }
+
+    fn row_center(&self, tile: IVec2) -> Vec2 {
+        let shift = if tile.y.rem_euclid(2) == self.shifted_parity {
+            self.cell.x / 2.
+        } else {
+            0.
+        };
+        Vec2::new(
+            tile.x as f32 * self.cell.x + self.cell.x / 2. + shift,
+            tile.y as f32 * (self.cell.y + self.side) / 2. + self.cell.y / 2.,
+        )
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use bevy::math::Vec2;
+    use bevy::math::{IVec2, UVec2, Vec2};
+    use bevy_ecs_tilemap::map::{HexCoordSystem, TilemapGridSize, TilemapType};
 
-    use crate::helpers::coordinate_utils::CoordinateOps;
+    use crate::helpers::coordinate_utils::{
+        CoordinateOps, MapOrientation, StaggerAxis, StaggerIndex, TileLayout,
+    };
 
     #[test]
     fn relative_to_should_relativize() {
@@ -119,4 +408,229 @@ mod tests {
         let expected = Vec2::new(-1000., 1.);
         assert_eq!(position.copy_signs(&signs), expected)
     }
+
+    #[test]
+    fn tile_pos_round_trips_through_world() {
+        let layout = TileLayout::new(
+            MapOrientation::Orthogonal,
+            Vec2::splat(64.),
+            UVec2::new(30, 23),
+        )
+        .at(Vec2::new(-960., -736.));
+
+        for (x, y) in [(0, 0), (5, 7), (29, 22)] {
+            let world = layout.tile_to_world(x, y);
+            assert_eq!(Some((x, y)), layout.tile_at(world))
+        }
+    }
+
+    #[test]
+    fn world_to_tile_pos_rejects_positions_outside_top_left() {
+        let layout = TileLayout::new(
+            MapOrientation::Orthogonal,
+            Vec2::splat(64.),
+            UVec2::new(30, 23),
+        );
+
+        assert_eq!(None, layout.tile_at(Vec2::new(-100., 0.)))
+    }
+
+    fn layouts() -> Vec<TileLayout> {
+        let staggers = [
+            (StaggerAxis::X, StaggerIndex::Odd),
+            (StaggerAxis::X, StaggerIndex::Even),
+            (StaggerAxis::Y, StaggerIndex::Odd),
+            (StaggerAxis::Y, StaggerIndex::Even),
+        ];
+        let orientations = [MapOrientation::Orthogonal, MapOrientation::Isometric]
+            .into_iter()
+            .chain(
+                staggers
+                    .iter()
+                    .map(|&(axis, index)| MapOrientation::Staggered { axis, index }),
+            )
+            .chain(
+                staggers
+                    .iter()
+                    .map(|&(axis, index)| MapOrientation::Hexagonal {
+                        axis,
+                        index,
+                        side_length: 14,
+                    }),
+            );
+        orientations
+            .flat_map(|orientation| {
+                [UVec2::new(5, 4), UVec2::new(4, 5)].map(|size| {
+                    TileLayout::new(orientation, Vec2::new(64., 32.), size)
+                        .at(Vec2::new(-100., 40.))
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_every_orientation_round_trips() {
+        for layout in layouts() {
+            for y in 0..layout.size.y as usize {
+                for x in 0..layout.size.x as usize {
+                    let center = layout.tile_to_world(x, y);
+                    // Well inside the cell, whatever its shape
+                    for offset in [Vec2::ZERO, Vec2::new(9., 0.), Vec2::new(-4., 4.)] {
+                        assert_eq!(
+                            Some((x, y)),
+                            layout.tile_at(center + offset),
+                            "{:?} tile {:?} offset {:?}",
+                            layout.orientation,
+                            (x, y),
+                            offset
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_isometric_rows_go_down_right() {
+        let layout = TileLayout::new(
+            MapOrientation::Isometric,
+            Vec2::new(64., 32.),
+            UVec2::new(5, 4),
+        );
+        let top = layout.tile_to_world(0, 0);
+
+        assert_eq!(Vec2::new(32., -16.), layout.tile_to_world(1, 0) - top);
+        assert_eq!(Vec2::new(-32., -16.), layout.tile_to_world(0, 1) - top);
+        // Diamond cells: the corner of the bounding box belongs to the neighbour
+        assert_eq!(Some((0, 0)), layout.tile_at(top + Vec2::new(15., -7.)));
+        assert_eq!(Some((1, 0)), layout.tile_at(top + Vec2::new(20., -10.)));
+    }
+
+    #[test]
+    fn test_staggered_shifts_every_other_row() {
+        let odd = TileLayout::new(
+            MapOrientation::Staggered {
+                axis: StaggerAxis::Y,
+                index: StaggerIndex::Odd,
+            },
+            Vec2::new(64., 32.),
+            UVec2::new(5, 4),
+        );
+        assert_eq!(
+            Vec2::new(32., -16.),
+            odd.tile_to_world(0, 1) - odd.tile_to_world(0, 0)
+        );
+        assert_eq!(
+            Vec2::new(-32., -16.),
+            odd.tile_to_world(0, 2) - odd.tile_to_world(0, 1)
+        );
+        assert_eq!(
+            Some((0, 1)),
+            odd.tile_at(odd.tile_to_world(0, 0) + Vec2::new(20., -10.))
+        );
+
+        let even = TileLayout {
+            orientation: MapOrientation::Staggered {
+                axis: StaggerAxis::Y,
+                index: StaggerIndex::Even,
+            },
+            ..odd
+        };
+        assert_eq!(
+            Vec2::new(-32., -16.),
+            even.tile_to_world(0, 1) - even.tile_to_world(0, 0)
+        );
+    }
+
+    #[test]
+    fn test_hexagonal_rows_and_columns_spacing() {
+        let rows = TileLayout::new(
+            MapOrientation::Hexagonal {
+                axis: StaggerAxis::Y,
+                index: StaggerIndex::Odd,
+                side_length: 14,
+            },
+            Vec2::new(32., 28.),
+            UVec2::new(5, 4),
+        );
+        assert_eq!(
+            Vec2::new(16., -21.),
+            rows.tile_to_world(0, 1) - rows.tile_to_world(0, 0)
+        );
+        assert_eq!(
+            Vec2::new(32., 0.),
+            rows.tile_to_world(1, 0) - rows.tile_to_world(0, 0)
+        );
+
+        let columns = TileLayout::new(
+            MapOrientation::Hexagonal {
+                axis: StaggerAxis::X,
+                index: StaggerIndex::Odd,
+                side_length: 14,
+            },
+            Vec2::new(28., 32.),
+            UVec2::new(5, 4),
+        );
+        // Tiled shifts staggered columns down
+        assert_eq!(
+            Vec2::new(21., -16.),
+            columns.tile_to_world(1, 0) - columns.tile_to_world(0, 0)
+        );
+    }
+
+    #[test]
+    fn test_hexagonal_rendering_spacing_and_parity() {
+        let layout = |axis, size| {
+            TileLayout::new(
+                MapOrientation::Hexagonal {
+                    axis,
+                    index: StaggerIndex::Odd,
+                    side_length: 14,
+                },
+                Vec2::new(32., 28.),
+                size,
+            )
+        };
+
+        let rows = layout(StaggerAxis::Y, UVec2::new(5, 4));
+        assert_eq!(TilemapGridSize { x: 32., y: 28. }, rows.grid_size());
+        // TMX row 1 is bevy_ecs_tilemap row 2 on a map 4 rows high
+        assert_eq!(
+            TilemapType::Hexagon(HexCoordSystem::RowEven),
+            rows.tilemap_type()
+        );
+        assert_eq!(
+            TilemapType::Hexagon(HexCoordSystem::RowOdd),
+            layout(StaggerAxis::Y, UVec2::new(5, 5)).tilemap_type()
+        );
+        assert_eq!(
+            TilemapType::Hexagon(HexCoordSystem::ColumnEven),
+            layout(StaggerAxis::X, UVec2::new(5, 4)).tilemap_type()
+        );
+    }
+
+    #[test]
+    fn test_objects_land_on_their_tile() {
+        let isometric = TileLayout::new(
+            MapOrientation::Isometric,
+            Vec2::new(64., 32.),
+            UVec2::new(5, 4),
+        );
+        // Isometric objects are placed along the tile axes, in tile height units
+        assert_eq!(
+            isometric.tile_to_world(2, 1),
+            isometric.object_to_world(Vec2::new(80., 48.), IVec2::ZERO)
+        );
+
+        // Infinite maps start at negative tiles
+        let orthogonal = TileLayout::new(
+            MapOrientation::Orthogonal,
+            Vec2::splat(16.),
+            UVec2::new(32, 32),
+        );
+        assert_eq!(
+            orthogonal.tile_to_world(0, 0),
+            orthogonal.object_to_world(Vec2::new(-248., 8.), IVec2::new(-16, 0))
+        );
+    }
 }
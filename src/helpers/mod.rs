@@ -1,2 +1,5 @@
+pub mod chunks;
 pub mod coordinate_utils;
+pub mod tile_animation;
 pub mod tiled;
+pub mod y_sort;
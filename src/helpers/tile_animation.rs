@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_ecs_tilemap::tiles::TileTextureIndex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileAnimationFrame {
+    pub texture_index: u32,
+    pub duration: Duration,
+}
+
+/// Tiled tile animation. Frames can be any tiles of the tileset, each shown for its own
+/// duration. Like in Tiled, every tile with the same animation shows the same frame.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct TileAnimation {
+    frames: Vec<TileAnimationFrame>,
+    length: Duration,
+}
+
+impl TileAnimation {
+    /// `None` when there is nothing to play.
+    pub fn new(frames: Vec<TileAnimationFrame>) -> Option<Self> {
+        let length = frames.iter().map(|frame| frame.duration).sum();
+        if length == Duration::ZERO {
+            return None;
+        }
+        Some(Self { frames, length })
+    }
+
+    pub fn frames(&self) -> &[TileAnimationFrame] {
+        &self.frames
+    }
+
+    /// Texture index shown `elapsed` after the start of the animation, looping.
+    pub fn texture_index_at(&self, elapsed: Duration) -> u32 {
+        let mut time = Duration::from_nanos((elapsed.as_nanos() % self.length.as_nanos()) as u64);
+        for frame in &self.frames {
+            if time < frame.duration {
+                return frame.texture_index;
+            }
+            time -= frame.duration;
+        }
+        // Only reachable through rounding, the loop covers the whole length
+        self.frames[self.frames.len() - 1].texture_index
+    }
+}
+
+pub fn animate_tiles(time: Res<Time>, mut tiles: Query<(&TileAnimation, &mut TileTextureIndex)>) {
+    let elapsed = time.elapsed();
+    for (animation, mut texture_index) in tiles.iter_mut() {
+        let current = animation.texture_index_at(elapsed);
+        // Avoid change detection re-extracting tiles that didn't change frame
+        if texture_index.0 != current {
+            texture_index.0 = current;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{TileAnimation, TileAnimationFrame};
+
+    fn frame(texture_index: u32, millis: u64) -> TileAnimationFrame {
+        TileAnimationFrame {
+            texture_index,
+            duration: Duration::from_millis(millis),
+        }
+    }
+
+    #[test]
+    fn test_frames_play_for_their_own_duration() {
+        let animation =
+            TileAnimation::new(vec![frame(7, 100), frame(2, 300), frame(7, 50)]).unwrap();
+        let at = |millis| animation.texture_index_at(Duration::from_millis(millis));
+
+        assert_eq!(7, at(0));
+        assert_eq!(7, at(99));
+        assert_eq!(2, at(100));
+        assert_eq!(2, at(399));
+        assert_eq!(7, at(400));
+        assert_eq!(7, at(449));
+    }
+
+    #[test]
+    fn test_animation_loops() {
+        let animation = TileAnimation::new(vec![frame(3, 100), frame(1, 200)]).unwrap();
+        let at = |millis| animation.texture_index_at(Duration::from_millis(millis));
+
+        assert_eq!(3, at(300));
+        assert_eq!(1, at(3 * 300 + 150));
+    }
+
+    #[test]
+    fn test_nothing_to_play() {
+        assert_eq!(None, TileAnimation::new(vec![]));
+        assert_eq!(None, TileAnimation::new(vec![frame(1, 0), frame(2, 0)]));
+    }
+}
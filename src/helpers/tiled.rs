@@ -1,23 +1,62 @@
 // https://github.com/StarArawn/bevy_ecs_tilemap/blob/main/examples/helpers/tiled.rs
 
-use std::io::Cursor;
-use std::path::Path;
-use std::sync::Arc;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use bevy::{
     asset::{AssetLoader, AssetPath, LoadedAsset},
     log::{self, warn},
+    math::{IVec2, UVec2, Vec2, Vec3, Vec3Swizzles},
     prelude::{
-        AddAsset, Added, AssetEvent, Assets, Bundle, Commands, Component, DespawnRecursiveExt,
-        Entity, EventReader, GlobalTransform, Handle, Image, Plugin, Query, Res, Transform, Update,
+        AddAsset, Added, AssetEvent, Assets, BuildChildren, Bundle, Camera, Color, Commands,
+        Component, DespawnRecursiveExt, Entity, EventReader, GlobalTransform, Handle, Image,
+        IntoSystemConfigs, Name, Plugin, PostUpdate, Query, Res, SpatialBundle, Transform, Update,
+        Visibility, With, Without,
     },
     reflect::{TypePath, TypeUuid},
+    transform::TransformSystem,
     utils::HashMap,
 };
 use bevy_ecs_tilemap::prelude::*;
 
 use anyhow::Result;
-use tiled::Tile;
+use tiled::{Properties, PropertyValue, Tile};
+
+use crate::helpers::{
+    chunks::{stream_chunks, stream_walkable_chunks, InfiniteLayer, CHUNK_SIZE},
+    coordinate_utils::{MapOrientation, StaggerAxis, StaggerIndex, TileLayout},
+    tile_animation::{animate_tiles, TileAnimation, TileAnimationFrame},
+    y_sort::{y_sort, YSort},
+};
+use crate::levels::{
+    elevation::{ElevationTiles, TileElevation},
+    objects::{is_spawn_point, spawn_object_layer},
+    BitGrid, Grid, WalkableTiles,
+};
+use crate::mover::KinematicSet;
+
+/// Tile property deciding walkability of a cell. Topmost layer defining it wins.
+const WALKABLE_PROPERTY: &str = "walkable";
+/// Layer property (or layer name) marking every tile on the layer as blocked.
+const COLLISION_LAYER: &str = "collision";
+/// Int layer property setting the floor of every tile on the layer.
+const FLOOR_PROPERTY: &str = "floor";
+/// Layer property marking its tiles as stairs/bridges linking `floor` with the one above.
+const CONNECTOR_PROPERTY: &str = "connector";
+/// Float or int layer property replacing the layer z, which is its index by default.
+const Z_PROPERTY: &str = "z";
+/// Float layer property replacing both of Tiled's parallax factors.
+const PARALLAX_PROPERTY: &str = "parallax";
+/// Float layer property replacing Tiled's layer opacity.
+const OPACITY_PROPERTY: &str = "opacity";
+/// Layer property replacing Tiled's layer visibility.
+const VISIBLE_PROPERTY: &str = "visible";
+/// Color layer property replacing Tiled's layer tint.
+const TINT_PROPERTY: &str = "tint";
+/// Layer property drawing the tiles of the layer in the y-sorted band, see
+/// [`SORTED_Z`](super::y_sort::SORTED_Z).
+const SORTED_PROPERTY: &str = "sorted";
 
 #[derive(Default)]
 pub struct TiledMapPlugin;
@@ -26,7 +65,23 @@ impl Plugin for TiledMapPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_asset::<TiledMap>()
             .add_asset_loader(TiledLoader)
-            .add_systems(Update, process_loaded_maps);
+            .add_systems(
+                Update,
+                (
+                    (remove_maps, process_loaded_maps).chain(),
+                    stream_chunks,
+                    // Entities only move over loaded walkability
+                    stream_walkable_chunks.before(KinematicSet::Move),
+                    animate_tiles,
+                ),
+            )
+            // After the camera and entities moved this frame
+            .add_systems(
+                PostUpdate,
+                (scroll_parallax_layers, y_sort)
+                    .chain()
+                    .before(TransformSystem::TransformPropagate),
+            );
     }
 }
 
@@ -35,7 +90,144 @@ impl Plugin for TiledMapPlugin {
 pub struct TiledMap {
     pub map: tiled::Map,
 
-    pub tilemap_textures: HashMap<usize, TilemapTexture>,
+    pub tilemap_textures: Vec<TilesetTexture>,
+
+    pub bounds: MapBounds,
+
+    /// Walkability derived from tile properties and collision layers, if the map defines any.
+    pub walkable_tiles: Option<WalkableTiles>,
+
+    /// Floors derived from `floor`/`connector` layer properties, if the map defines any.
+    pub elevation: Option<ElevationTiles>,
+
+    /// Tile of the `spawn` object, if the map has one.
+    pub spawn_point: Option<Vec2>,
+}
+
+impl TiledMap {
+    /// Derives the level data of `map`, tools without an asset server leave textures empty.
+    pub fn new(map: tiled::Map, tilemap_textures: Vec<TilesetTexture>) -> Self {
+        Self {
+            bounds: MapBounds::of(&map),
+            walkable_tiles: derive_walkable_tiles(&map),
+            elevation: derive_elevation_tiles(&map),
+            spawn_point: derive_spawn_point(&map),
+            map,
+            tilemap_textures,
+        }
+    }
+}
+
+/// Texture of the tiles of a tileset. Image collections get one per distinct image size,
+/// since all the tiles of a tilemap have to be the same size.
+#[derive(Clone)]
+pub struct TilesetTexture {
+    pub tileset_index: usize,
+    pub texture: TilemapTexture,
+    pub tile_size: TilemapTileSize,
+    pub spacing: TilemapSpacing,
+    /// Index into `texture` of image collection tiles, atlas tiles are indexed by their id
+    image_indices: HashMap<tiled::TileId, u32>,
+}
+
+impl TilesetTexture {
+    fn image_collection(tileset_index: usize, tile_size: TilemapTileSize) -> Self {
+        Self {
+            tileset_index,
+            texture: TilemapTexture::Vector(Vec::new()),
+            tile_size,
+            spacing: TilemapSpacing::default(),
+            image_indices: HashMap::default(),
+        }
+    }
+
+    fn push_image(&mut self, tile_id: tiled::TileId, image: Handle<Image>) {
+        if let TilemapTexture::Vector(images) = &mut self.texture {
+            self.image_indices.insert(tile_id, images.len() as u32);
+            images.push(image);
+        }
+    }
+
+    /// `None` when the tile is drawn from another texture.
+    pub fn texture_index(&self, layer_tile: &tiled::LayerTile) -> Option<TileTextureIndex> {
+        if layer_tile.tileset_index() != self.tileset_index {
+            return None;
+        }
+        self.tile_texture_index(layer_tile.id())
+            .map(TileTextureIndex)
+    }
+
+    /// Index into `texture` of a tile of this texture's tileset.
+    fn tile_texture_index(&self, tile_id: tiled::TileId) -> Option<u32> {
+        match &self.texture {
+            TilemapTexture::Vector(_) => self.image_indices.get(&tile_id).copied(),
+            _ => Some(tile_id),
+        }
+    }
+
+    /// Offset from the cell center to the tile center. Tiled anchors tiles bigger than the map
+    /// tiles at the bottom left corner of their cell (bottom center on isometric maps), while
+    /// bevy_ecs_tilemap centers them.
+    pub fn anchor_offset(&self, layout: &TileLayout) -> Vec2 {
+        let overflow = Vec2::new(self.tile_size.x, self.tile_size.y) - layout.tile_size;
+        match layout.orientation {
+            MapOrientation::Isometric => Vec2::new(0., overflow.y / 2.),
+            _ => overflow / 2.,
+        }
+    }
+}
+
+/// Tiles covered by the map in TMX coordinates. Infinite maps are bounded by their chunks
+/// and may start at negative coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapBounds {
+    pub min: IVec2,
+    pub size: UVec2,
+}
+
+impl MapBounds {
+    pub fn of(map: &tiled::Map) -> Self {
+        let mut min = IVec2::MAX;
+        let mut max = IVec2::MIN;
+        for layer in map.layers() {
+            let tiled::LayerType::Tiles(tiled::TileLayer::Infinite(layer_data)) =
+                layer.layer_type()
+            else {
+                continue;
+            };
+            for (chunk_pos, _) in layer_data.chunks() {
+                min = min.min(IVec2::from(chunk_pos));
+                max = max.max(IVec2::from(chunk_pos));
+            }
+        }
+
+        if min.x > max.x {
+            return Self {
+                min: IVec2::ZERO,
+                size: UVec2::new(map.width, map.height),
+            };
+        }
+        let chunk_size = CHUNK_SIZE as i32;
+        Self {
+            min: min * chunk_size,
+            size: ((max - min + IVec2::ONE) * chunk_size).as_uvec2(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.size.x as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.size.y as usize
+    }
+
+    pub fn tilemap_size(&self) -> TilemapSize {
+        TilemapSize {
+            x: self.size.x,
+            y: self.size.y,
+        }
+    }
 }
 
 // Stores a list of tiled layers.
@@ -52,25 +244,120 @@ pub struct TiledMapBundle {
     pub global_transform: GlobalTransform,
 }
 
-struct BytesResourceReader {
-    bytes: Arc<[u8]>,
+/// Despawns the map entity along with all its layers, which aren't its children.
+#[derive(Component)]
+pub struct RemoveMap;
+
+/// How a layer is drawn: Tiled's layer attributes, replaced by the layer properties defining
+/// them. Tile layers keep it for the tilemaps spawned later on.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct LayerRendering {
+    /// Layers are drawn in Tiled order, one z apart, unless they set `z`
+    pub z: f32,
+    pub parallax: Vec2,
+    pub visible: bool,
+    /// Tint with the layer opacity as alpha
+    pub tint: Color,
+    /// Tiles are sorted with y-sorted entities by row, in place of the layer z
+    pub sorted: bool,
 }
 
-impl BytesResourceReader {
-    fn new(bytes: &[u8]) -> Self {
+impl LayerRendering {
+    pub fn of(layer: &tiled::Layer, layer_index: usize) -> Self {
+        let properties = &layer.properties;
+        let z = match properties.get(Z_PROPERTY) {
+            Some(PropertyValue::FloatValue(z)) => *z,
+            Some(PropertyValue::IntValue(z)) => *z as f32,
+            _ => layer_index as f32,
+        };
+        let parallax = float_property(properties, PARALLAX_PROPERTY)
+            .map(Vec2::splat)
+            .unwrap_or(Vec2::new(layer.parallax_x, layer.parallax_y));
+        let opacity = float_property(properties, OPACITY_PROPERTY).unwrap_or(layer.opacity);
+        let tint = match properties.get(TINT_PROPERTY) {
+            Some(PropertyValue::ColorValue(color)) => Some(*color),
+            _ => layer.tint_color,
+        }
+        .map_or(Color::WHITE, |c| {
+            Color::rgba_u8(c.red, c.green, c.blue, c.alpha)
+        });
+
         Self {
-            bytes: Arc::from(bytes),
+            z,
+            parallax,
+            visible: bool_property(properties, VISIBLE_PROPERTY).unwrap_or(layer.visible),
+            tint: tint.with_a(tint.a() * opacity),
+            sorted: bool_property(properties, SORTED_PROPERTY).unwrap_or(false),
         }
     }
+
+    fn visibility(&self) -> Visibility {
+        if self.visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        }
+    }
+}
+
+/// Layer scrolling at `factor` times the camera speed. `origin` is where it is drawn while the
+/// camera is at the world origin. Its tiles don't line up with the map anymore, gameplay
+/// shouldn't use its layout.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct LayerParallax {
+    pub factor: Vec2,
+    pub origin: Vec2,
 }
 
-impl tiled::ResourceReader for BytesResourceReader {
-    type Resource = Cursor<Arc<[u8]>>;
+/// Serves the map and the files it references (external tilesets, templates) from bytes
+/// fetched beforehand, see [`load_tmx_map`].
+struct PrefetchedResourceReader<'a> {
+    files: &'a HashMap<PathBuf, Vec<u8>>,
+}
+
+impl<'a> tiled::ResourceReader for PrefetchedResourceReader<'a> {
+    type Resource = &'a [u8];
     type Error = std::io::Error;
 
-    fn read_from(&mut self, _path: &Path) -> std::result::Result<Self::Resource, Self::Error> {
-        // In this case, the path is ignored because the byte data is already provided.
-        Ok(Cursor::new(self.bytes.clone()))
+    fn read_from(&mut self, path: &Path) -> std::result::Result<Self::Resource, Self::Error> {
+        self.files.get(path).map(Vec::as_slice).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} isn't fetched yet", path.display()),
+            )
+        })
+    }
+}
+
+/// Parses the map at `path`, fetching every file it references with `read`. tiled reads files
+/// synchronously, so the map is parsed again after each missing file is fetched.
+pub async fn load_tmx_map<F, Fut>(path: &Path, bytes: &[u8], mut read: F) -> Result<tiled::Map>
+where
+    F: FnMut(PathBuf) -> Fut,
+    Fut: Future<Output = Result<Vec<u8>>>,
+{
+    let mut files = HashMap::default();
+    files.insert(path.to_path_buf(), bytes.to_vec());
+
+    loop {
+        let result = tiled::Loader::with_cache_and_reader(
+            tiled::DefaultResourceCache::new(),
+            PrefetchedResourceReader { files: &files },
+        )
+        .load_tmx_map(path);
+
+        match result {
+            Ok(map) => return Ok(map),
+            Err(tiled::Error::ResourceLoadingError { path: missing, .. })
+                if !files.contains_key(&missing) =>
+            {
+                let bytes = read(missing.clone())
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Could not read {}: {e}", missing.display()))?;
+                files.insert(missing, bytes);
+            }
+            Err(e) => return Err(anyhow::anyhow!("Could not load TMX map: {e}")),
+        }
     }
 }
 
@@ -83,39 +370,77 @@ impl AssetLoader for TiledLoader {
         load_context: &'a mut bevy::asset::LoadContext,
     ) -> bevy::asset::BoxedFuture<'a, Result<()>> {
         Box::pin(async move {
-            let mut loader = tiled::Loader::with_cache_and_reader(
-                tiled::DefaultResourceCache::new(),
-                BytesResourceReader::new(bytes),
-            );
-            let map = loader
-                .load_tmx_map(load_context.path())
-                .map_err(|e| anyhow::anyhow!("Could not load TMX map: {e}"))?;
+            let context: &bevy::asset::LoadContext = load_context;
+            // Reading through the asset IO also watches the file, so changes to a shared
+            // tileset or template reload the map
+            let map = load_tmx_map(context.path(), bytes, |path| async move {
+                Ok::<_, anyhow::Error>(context.read_asset_bytes(path).await?)
+            })
+            .await?;
 
             let mut dependencies = Vec::new();
-            let mut tilemap_textures = HashMap::default();
+            let mut tilemap_textures = Vec::new();
 
             for (tileset_index, tileset) in map.tilesets().iter().enumerate() {
-                let tilemap_texture = match &tileset.image {
+                match &tileset.image {
                     None => {
-                        log::info!("Skipping image collection tileset '{}' which is incompatible with atlas feature", tileset.name);
-                        continue;
+                        let mut collection: Vec<TilesetTexture> = Vec::new();
+                        for (tile_id, tile) in tileset.tiles() {
+                            let Some(img) = &tile.image else {
+                                continue;
+                            };
+                            let asset_path = AssetPath::new(img.source.clone(), None);
+                            let texture: Handle<Image> =
+                                load_context.get_handle(asset_path.clone());
+                            dependencies.push(asset_path);
+
+                            let tile_size = TilemapTileSize {
+                                x: img.width as f32,
+                                y: img.height as f32,
+                            };
+                            match collection.iter_mut().find(|t| t.tile_size == tile_size) {
+                                Some(tileset_texture) => {
+                                    tileset_texture.push_image(tile_id, texture)
+                                }
+                                None => {
+                                    let mut tileset_texture =
+                                        TilesetTexture::image_collection(tileset_index, tile_size);
+                                    tileset_texture.push_image(tile_id, texture);
+                                    collection.push(tileset_texture);
+                                }
+                            }
+                        }
+                        tilemap_textures.extend(collection);
                     }
                     Some(img) => {
                         let asset_path = AssetPath::new(img.source.clone(), None);
                         let texture: Handle<Image> = load_context.get_handle(asset_path.clone());
                         dependencies.push(asset_path);
 
-                        TilemapTexture::Single(texture.clone())
+                        tilemap_textures.push(TilesetTexture {
+                            tileset_index,
+                            texture: TilemapTexture::Single(texture),
+                            tile_size: TilemapTileSize {
+                                x: tileset.tile_width as f32,
+                                y: tileset.tile_height as f32,
+                            },
+                            spacing: TilemapSpacing {
+                                x: tileset.spacing as f32,
+                                y: tileset.spacing as f32,
+                            },
+                            image_indices: HashMap::default(),
+                        });
                     }
                 };
-
-                tilemap_textures.insert(tileset_index, tilemap_texture);
             }
 
-            let asset_map = TiledMap {
-                map,
-                tilemap_textures,
-            };
+            let asset_map = TiledMap::new(map, tilemap_textures);
+            if asset_map.walkable_tiles.is_none() {
+                log::info!(
+                    "Map {} has no walkability data, expecting it in the level config",
+                    load_context.path().display()
+                );
+            }
 
             log::info!("Loaded map: {}", load_context.path().display());
 
@@ -135,9 +460,8 @@ pub fn process_loaded_maps(
     mut commands: Commands,
     mut map_events: EventReader<AssetEvent<TiledMap>>,
     maps: Res<Assets<TiledMap>>,
-    tile_storage_query: Query<(Entity, &TileStorage)>,
-    mut map_query: Query<(&Handle<TiledMap>, &mut TiledLayersStorage)>,
-    new_maps: Query<&Handle<TiledMap>, Added<Handle<TiledMap>>>,
+    mut map_query: Query<(&Handle<TiledMap>, &mut TiledLayersStorage), Without<RemoveMap>>,
+    new_maps: Query<&Handle<TiledMap>, (Added<Handle<TiledMap>>, Without<RemoveMap>)>,
 ) {
     let mut changed_maps = Vec::<Handle<TiledMap>>::default();
     for event in map_events.iter() {
@@ -154,14 +478,16 @@ pub fn process_loaded_maps(
                 log::info!("Map removed!");
                 // if mesh was modified and removed in the same update, ignore the modification
                 // events are ordered so future modification events are ok
-                changed_maps.retain(|changed_handle| changed_handle == handle);
+                changed_maps.retain(|changed_handle| changed_handle != handle);
             }
         }
     }
 
     // If we have new map entities add them to the changed_maps list.
     for new_map_handle in new_maps.iter() {
-        changed_maps.push(new_map_handle.clone_weak());
+        if !changed_maps.contains(new_map_handle) {
+            changed_maps.push(new_map_handle.clone_weak());
+        }
     }
 
     for changed_map in changed_maps.iter() {
@@ -171,201 +497,736 @@ pub fn process_loaded_maps(
                 continue;
             }
             if let Some(tiled_map) = maps.get(map_handle) {
-                // TODO: Create a RemoveMap component..
-                for layer_entity in layer_storage.storage.values() {
-                    if let Ok((_, layer_tile_storage)) = tile_storage_query.get(*layer_entity) {
-                        for tile in layer_tile_storage.iter().flatten() {
-                            commands.entity(*tile).despawn_recursive()
+                // Rebuild from scratch, layers may have been added, removed or reordered
+                despawn_map_layers(&mut commands, &layer_storage);
+                layer_storage.storage.clear();
+
+                let map_size = tiled_map.bounds.tilemap_size();
+                let layout = map_layout(&tiled_map.map, &tiled_map.bounds);
+                let grid_size = layout.grid_size();
+                let map_type = layout.tilemap_type();
+
+                for (layer_index, layer) in tiled_map.map.layers().enumerate() {
+                    let tiled::LayerType::Tiles(tile_layer) = layer.layer_type() else {
+                        // Object layers are spawned separately below
+                        if !matches!(layer.layer_type(), tiled::LayerType::Objects(_)) {
+                            log::info!(
+                                "Skipping layer {} because only tile and object layers are supported.",
+                                layer.id()
+                            );
+                        }
+                        continue;
+                    };
+
+                    let rendering = LayerRendering::of(&layer, layer_index);
+                    let transform =
+                        get_tilemap_center_transform(&map_size, &grid_size, &map_type, rendering.z)
+                            * Transform::from_xyz(layer.offset_x, -layer.offset_y, 0.0);
+
+                    // The TilemapBundle requires that all tile images come exclusively from a
+                    // single tiled texture or from a Vec of independent per-tile images of the
+                    // same size. The layer entity only holds the layout, every tileset texture
+                    // used on the layer gets its own child tilemap.
+                    let layer_entity =
+                        spawn_layer(&mut commands, &layer.name, &rendering, transform);
+                    commands.entity(layer_entity).insert((layout, rendering));
+                    layer_storage
+                        .storage
+                        .insert(layer_index as u32, layer_entity);
+
+                    let layer_data = match tile_layer {
+                        tiled::TileLayer::Finite(layer_data) => layer_data,
+                        tiled::TileLayer::Infinite(_) => {
+                            // Chunks are spawned around the camera by `stream_chunks`
+                            commands
+                                .entity(layer_entity)
+                                .insert(InfiniteLayer::new(map_handle.clone_weak(), layer_index));
+                            continue;
                         }
+                    };
+
+                    let texture_count = tiled_map.tilemap_textures.len();
+                    for (texture_index, tileset_texture) in
+                        tiled_map.tilemap_textures.iter().enumerate()
+                    {
+                        let tilemaps = spawn_layer_tilemaps(
+                            &mut commands,
+                            tileset_texture,
+                            map_size,
+                            &layout,
+                            Vec2::ZERO.extend(tilemap_z(texture_index, texture_count)),
+                            &rendering,
+                            |tile_pos| {
+                                // Transform bevy coords into TMX coords.
+                                layer_data.get_tile(
+                                    tile_pos.x as i32,
+                                    (tiled_map.map.height - 1 - tile_pos.y) as i32,
+                                )
+                            },
+                        );
+                        commands.entity(layer_entity).push_children(&tilemaps);
                     }
-                    // commands.entity(*layer_entity).despawn_recursive();
                 }
 
-                // The TilemapBundle requires that all tile images come exclusively from a single
-                // tiled texture or from a Vec of independent per-tile images. Furthermore, all of
-                // the per-tile images must be the same size. Since Tiled allows tiles of mixed
-                // tilesets on each layer and allows differently-sized tile images in each tileset,
-                // this means we need to load each combination of tileset and layer separately.
-                for (tileset_index, tileset) in tiled_map.map.tilesets().iter().enumerate() {
-                    let Some(tilemap_texture) = tiled_map.tilemap_textures.get(&tileset_index)
-                    else {
-                        log::warn!("Skipped creating layer with missing tilemap textures.");
+                for (layer_index, layer) in tiled_map.map.layers().enumerate() {
+                    let tiled::LayerType::Objects(object_layer) = layer.layer_type() else {
                         continue;
                     };
 
-                    let tile_size = TilemapTileSize {
-                        x: tileset.tile_width as f32,
-                        y: tileset.tile_height as f32,
-                    };
+                    // Objects line up with the tiles of a layer at the same place
+                    let center = get_tilemap_center_transform(&map_size, &grid_size, &map_type, 0.);
+                    let object_layout = layout.at(center.translation.xy());
+                    // Objects are drawn by their prefabs, which don't get the opacity and tint
+                    let rendering = LayerRendering::of(&layer, layer_index);
+                    let origin = Transform::from_xyz(layer.offset_x, -layer.offset_y, rendering.z);
 
-                    let tile_spacing = TilemapSpacing {
-                        x: tileset.spacing as f32,
-                        y: tileset.spacing as f32,
-                    };
+                    let layer_entity = spawn_layer(&mut commands, &layer.name, &rendering, origin);
+                    spawn_object_layer(&mut commands, layer_entity, &object_layer, |position| {
+                        object_layout.object_to_world(position, tiled_map.bounds.min)
+                    });
+                    layer_storage
+                        .storage
+                        .insert(layer_index as u32, layer_entity);
+                }
+            }
+        }
+    }
+}
 
-                    // Once materials have been created/added we need to then create the layers.
-                    for (layer_index, layer) in tiled_map.map.layers().enumerate() {
-                        let offset_x = layer.offset_x;
-                        let offset_y = layer.offset_y;
+/// Rendered part of a tile layer: the tiles of one [`TilesetTexture`], for the whole layer or a
+/// chunk of it. Child of the layer entity, which holds the layout used for coordinate conversions.
+#[derive(Component)]
+pub struct LayerTilemap;
 
-                        let tiled::LayerType::Tiles(tile_layer) = layer.layer_type() else {
-                            log::info!(
-                                "Skipping layer {} because only tile layers are supported.",
-                                layer.id()
-                            );
-                            continue;
-                        };
+/// Spawns an empty layer entity at `transform`, hidden or scrolling as `rendering` says.
+fn spawn_layer(
+    commands: &mut Commands,
+    name: &str,
+    rendering: &LayerRendering,
+    transform: Transform,
+) -> Entity {
+    let mut layer = commands.spawn((
+        Name::new(name.to_string()),
+        SpatialBundle {
+            transform,
+            visibility: rendering.visibility(),
+            ..Default::default()
+        },
+    ));
+    if rendering.parallax != Vec2::ONE {
+        layer.insert(LayerParallax {
+            factor: rendering.parallax,
+            origin: transform.translation.xy(),
+        });
+    }
+    layer.id()
+}
 
-                        let tiled::TileLayer::Finite(layer_data) = tile_layer else {
-                            log::info!(
-                                "Skipping layer {} because only finite layers are supported.",
-                                layer.id()
-                            );
-                            continue;
-                        };
+/// Local z of the tilemap of the `texture_index`th of `texture_count` tileset textures. Tilemaps
+/// of a layer are drawn in tileset order, below the next layer.
+pub fn tilemap_z(texture_index: usize, texture_count: usize) -> f32 {
+    texture_index as f32 / texture_count as f32
+}
 
-                        let map_size = TilemapSize {
-                            x: tiled_map.map.width,
-                            y: tiled_map.map.height,
-                        };
+/// Spawns the tiles `tile_at` returns for `tileset_texture` as a tilemap of `size` whose bottom
+/// left cell is centered on `origin`, relative to the layer. Sorted layers get one tilemap per
+/// row of cells instead, sorted with y-sorted entities. Empty when there are no tiles.
+pub fn spawn_layer_tilemaps<'map>(
+    commands: &mut Commands,
+    tileset_texture: &TilesetTexture,
+    size: TilemapSize,
+    layout: &TileLayout,
+    origin: Vec3,
+    rendering: &LayerRendering,
+    tile_at: impl Fn(TilePos) -> Option<tiled::LayerTile<'map>>,
+) -> Vec<Entity> {
+    let grid_size = layout.grid_size();
+    let map_type = layout.tilemap_type();
+    // Tiles of each tilemap, keyed by the y of their cell center when sorting
+    let mut tilemaps: HashMap<u32, Vec<_>> = HashMap::default();
 
-                        let grid_size = TilemapGridSize {
-                            x: tiled_map.map.tile_width as f32,
-                            y: tiled_map.map.tile_height as f32,
-                        };
+    for x in 0..size.x {
+        for y in 0..size.y {
+            let tile_pos = TilePos { x, y };
+            let Some(layer_tile) = tile_at(tile_pos) else {
+                continue;
+            };
+            let Some(texture_index) = tileset_texture.texture_index(&layer_tile) else {
+                continue;
+            };
+            let key = if rendering.sorted {
+                tile_pos.center_in_world(&grid_size, &map_type).y.to_bits()
+            } else {
+                0
+            };
+            tilemaps
+                .entry(key)
+                .or_default()
+                .push((tile_pos, texture_index, layer_tile));
+        }
+    }
 
-                        let map_type = match tiled_map.map.orientation {
-                            tiled::Orientation::Hexagonal => {
-                                TilemapType::Hexagon(HexCoordSystem::Row)
-                            }
-                            tiled::Orientation::Isometric => {
-                                TilemapType::Isometric(IsoCoordSystem::Diamond)
-                            }
-                            tiled::Orientation::Staggered => {
-                                TilemapType::Isometric(IsoCoordSystem::Staggered)
-                            }
-                            tiled::Orientation::Orthogonal => TilemapType::Square,
-                        };
-
-                        let mut tile_storage = TileStorage::empty(map_size);
-                        let layer_entity = commands.spawn_empty().id();
-
-                        for x in 0..map_size.x {
-                            for y in 0..map_size.y {
-                                // Transform TMX coords into bevy coords.
-                                let mapped_y = tiled_map.map.height - 1 - y;
-
-                                let mapped_x = x as i32;
-                                let mapped_y = mapped_y as i32;
-
-                                let layer_tile = match layer_data.get_tile(mapped_x, mapped_y) {
-                                    Some(t) => t,
-                                    None => {
-                                        continue;
-                                    }
-                                };
-                                if tileset_index != layer_tile.tileset_index() {
-                                    continue;
-                                }
-                                let layer_tile_data =
-                                    match layer_data.get_tile_data(mapped_x, mapped_y) {
-                                        Some(d) => d,
-                                        None => {
-                                            continue;
-                                        }
-                                    };
-
-                                let texture_index = match tilemap_texture {
-                                    TilemapTexture::Single(_) => layer_tile.id(),
-                                };
-
-                                let tile_pos = TilePos { x, y };
-                                let tile = TileBundle {
-                                    position: tile_pos,
-                                    tilemap_id: TilemapId(layer_entity),
-                                    texture_index: TileTextureIndex(texture_index),
-                                    flip: TileFlip {
-                                        x: layer_tile_data.flip_h,
-                                        y: layer_tile_data.flip_v,
-                                        d: layer_tile_data.flip_d,
-                                    },
-                                    ..Default::default()
-                                };
-                                let animation = layer_tile.get_tile().and_then(get_animation);
-
-                                let tile_entity = match animation {
-                                    Some(a) => commands.spawn((tile, a)),
-                                    None => commands.spawn(tile),
-                                }
-                                .id();
+    let anchor = tileset_texture.anchor_offset(layout);
+    tilemaps
+        .into_iter()
+        .map(|(key, tiles)| {
+            let tilemap_entity = commands.spawn_empty().id();
+            let mut tile_storage = TileStorage::empty(size);
+            let tiles: Vec<Entity> = tiles
+                .into_iter()
+                .map(|(tile_pos, texture_index, layer_tile)| {
+                    let tile_entity = spawn_layer_tile(
+                        commands,
+                        tilemap_entity,
+                        tile_pos,
+                        texture_index,
+                        tileset_texture,
+                        &layer_tile,
+                    );
+                    tile_storage.set(&tile_pos, tile_entity);
+                    tile_entity
+                })
+                .collect();
 
-                                tile_storage.set(&tile_pos, tile_entity);
-                            }
-                        }
+            let mut tilemap = commands.entity(tilemap_entity);
+            tilemap
+                .insert((
+                    TilemapBundle {
+                        grid_size,
+                        size,
+                        storage: tile_storage,
+                        texture: tileset_texture.texture.clone(),
+                        tile_size: tileset_texture.tile_size,
+                        spacing: tileset_texture.spacing,
+                        transform: Transform::from_translation(origin + anchor.extend(0.)),
+                        map_type,
+                        tint: TilemapTint(rendering.tint),
+                        ..Default::default()
+                    },
+                    LayerTilemap,
+                ))
+                // Tiles aren't children of their tilemap by default, this way they get despawned
+                // with it
+                .push_children(&tiles);
+            if rendering.sorted {
+                // Feet of the row are the bottom of its cells, which big tiles are anchored to
+                let foot = f32::from_bits(key) - layout.tile_size.y / 2.;
+                tilemap.insert(YSort::new(foot - anchor.y));
+            }
+            tilemap_entity
+        })
+        .collect()
+}
 
-                        commands.entity(layer_entity).insert(TilemapBundle {
-                            grid_size,
-                            size: map_size,
-                            storage: tile_storage,
-                            texture: tilemap_texture.clone(),
-                            tile_size,
-                            spacing: tile_spacing,
-                            transform: get_tilemap_center_transform(
-                                &map_size,
-                                &grid_size,
-                                &map_type,
-                                layer_index as f32,
-                            ) * Transform::from_xyz(offset_x, -offset_y, 0.0),
-                            map_type,
-                            ..Default::default()
-                        });
+pub fn scroll_parallax_layers(
+    camera: Query<&Transform, (With<Camera>, Without<LayerParallax>)>,
+    mut layers: Query<(&LayerParallax, &mut Transform)>,
+) {
+    let Some(camera_translation) = camera.iter().next().map(|t| t.translation.xy()) else {
+        return;
+    };
+    for (parallax, mut transform) in layers.iter_mut() {
+        let translation = parallax.origin + camera_translation * (Vec2::ONE - parallax.factor);
+        transform.translation = translation.extend(transform.translation.z);
+    }
+}
 
-                        layer_storage
-                            .storage
-                            .insert(layer_index as u32, layer_entity);
-                    }
+pub fn remove_maps(
+    mut commands: Commands,
+    maps: Query<(Entity, &TiledLayersStorage), With<RemoveMap>>,
+) {
+    for (map_entity, layer_storage) in maps.iter() {
+        despawn_map_layers(&mut commands, layer_storage);
+        commands.entity(map_entity).despawn_recursive();
+    }
+}
+
+/// Despawns every layer of a map along with its tilemaps, chunks and objects.
+pub fn despawn_map_layers(commands: &mut Commands, layer_storage: &TiledLayersStorage) {
+    for layer_entity in layer_storage.storage.values() {
+        commands.entity(*layer_entity).despawn_recursive();
+    }
+}
+
+/// Spawns `layer_tile` at `tile_pos` of `tilemap`, animated when its tileset defines an animation.
+fn spawn_layer_tile(
+    commands: &mut Commands,
+    tilemap: Entity,
+    tile_pos: TilePos,
+    texture_index: TileTextureIndex,
+    tileset_texture: &TilesetTexture,
+    layer_tile: &tiled::LayerTile,
+) -> Entity {
+    let tile = TileBundle {
+        position: tile_pos,
+        tilemap_id: TilemapId(tilemap),
+        texture_index,
+        flip: TileFlip {
+            x: layer_tile.flip_h,
+            y: layer_tile.flip_v,
+            d: layer_tile.flip_d,
+        },
+        ..Default::default()
+    };
+    let animation = layer_tile
+        .get_tile()
+        .and_then(|tile| get_animation(&tile, tileset_texture));
+
+    match animation {
+        Some(a) => commands.spawn((tile, a)),
+        None => commands.spawn(tile),
+    }
+    .id()
+}
+
+/// Tile at TMX coordinates (x, y) of a finite or infinite layer.
+fn layer_tile_at<'map>(
+    layer_data: &tiled::TileLayer<'map>,
+    x: i32,
+    y: i32,
+) -> Option<tiled::LayerTile<'map>> {
+    match layer_data {
+        tiled::TileLayer::Finite(layer_data) => layer_data.get_tile(x, y),
+        tiled::TileLayer::Infinite(layer_data) => layer_data.get_tile(x, y),
+    }
+}
+
+fn bool_property(properties: &Properties, name: &str) -> Option<bool> {
+    match properties.get(name) {
+        Some(PropertyValue::BoolValue(value)) => Some(*value),
+        _ => None,
+    }
+}
+
+fn float_property(properties: &Properties, name: &str) -> Option<f32> {
+    match properties.get(name) {
+        Some(PropertyValue::FloatValue(value)) => Some(*value),
+        _ => None,
+    }
+}
+
+fn int_property(properties: &Properties, name: &str) -> Option<i32> {
+    match properties.get(name) {
+        Some(PropertyValue::IntValue(value)) => Some(*value),
+        _ => None,
+    }
+}
+
+fn is_collision_layer(layer: &tiled::Layer) -> bool {
+    layer.name == COLLISION_LAYER
+        || bool_property(&layer.properties, COLLISION_LAYER).unwrap_or(false)
+}
+
+/// Walkable grid in TMX coordinates relative to [`MapBounds::min`]: `walkable` tile properties
+/// decide cells (topmost layer wins) and any tile on a collision layer blocks its cell. Undecided
+/// cells are blocked, unless the map only has collision layers. `None` when the map has neither.
+pub fn derive_walkable_tiles(map: &tiled::Map) -> Option<WalkableTiles> {
+    let bounds = MapBounds::of(map);
+    let (width, height) = (bounds.width(), bounds.height());
+    let mut walkable = BitGrid::filled(height, width, false);
+    let mut collisions = BitGrid::filled(height, width, false);
+    let mut has_properties = false;
+    let mut has_collisions = false;
+
+    for layer in map.layers() {
+        let tiled::LayerType::Tiles(layer_data) = layer.layer_type() else {
+            continue;
+        };
+        let is_collision = is_collision_layer(&layer);
+
+        for y in 0..height {
+            for x in 0..width {
+                let Some(layer_tile) = layer_tile_at(
+                    &layer_data,
+                    bounds.min.x + x as i32,
+                    bounds.min.y + y as i32,
+                ) else {
+                    continue;
+                };
+
+                if is_collision {
+                    has_collisions = true;
+                    let _ = collisions.set(x, y, true);
+                } else if let Some(walkable_property) = layer_tile
+                    .get_tile()
+                    .and_then(|tile| bool_property(&tile.properties, WALKABLE_PROPERTY))
+                {
+                    has_properties = true;
+                    let _ = walkable.set(x, y, walkable_property);
                 }
             }
         }
     }
-}
 
-fn get_animation(tile: Tile) -> Option<AnimatedTile> {
-    // no animation
-    if tile.animation.is_none() {
+    if !has_properties && !has_collisions {
         return None;
     }
 
+    if !has_properties {
+        walkable = BitGrid::filled(height, width, true);
+    }
+    collisions.for_each(|x, y, &blocked| {
+        if blocked {
+            let _ = walkable.set(x, y, false);
+        }
+    });
+    Some(WalkableTiles::from(walkable))
+}
+
+/// Tile floors laid out like [`derive_walkable_tiles`], topmost layer with a `floor` or
+/// `connector` property wins. `None` when no layer has them.
+pub fn derive_elevation_tiles(map: &tiled::Map) -> Option<ElevationTiles> {
+    let bounds = MapBounds::of(map);
+    let (width, height) = (bounds.width(), bounds.height());
+    let mut elevation = Grid::new(height, width, TileElevation::default());
+    let mut has_data = false;
+
+    for layer in map.layers() {
+        let floor = int_property(&layer.properties, FLOOR_PROPERTY);
+        let connector = bool_property(&layer.properties, CONNECTOR_PROPERTY).unwrap_or(false);
+        if floor.is_none() && !connector {
+            continue;
+        }
+        let tiled::LayerType::Tiles(layer_data) = layer.layer_type() else {
+            log::warn!(
+                "Layer {} has elevation properties but isn't a tile layer",
+                layer.name
+            );
+            continue;
+        };
+
+        has_data = true;
+        let tile_elevation = TileElevation {
+            floor: floor.unwrap_or(0).clamp(0, u8::MAX as i32) as u8,
+            connector,
+        };
+        for y in 0..height {
+            for x in 0..width {
+                if layer_tile_at(
+                    &layer_data,
+                    bounds.min.x + x as i32,
+                    bounds.min.y + y as i32,
+                )
+                .is_some()
+                {
+                    let _ = elevation.set(x, y, tile_elevation);
+                }
+            }
+        }
+    }
+
+    has_data.then(|| ElevationTiles::from(elevation))
+}
+
+/// Tile coordinates of the first point named `spawn`, relative to [`MapBounds::min`].
+pub fn derive_spawn_point(map: &tiled::Map) -> Option<Vec2> {
+    let bounds = MapBounds::of(map);
+    let layout = map_layout(map, &bounds);
+    for layer in map.layers() {
+        let tiled::LayerType::Objects(object_layer) = layer.layer_type() else {
+            continue;
+        };
+        if let Some(spawn) = object_layer.objects().find(|object| is_spawn_point(object)) {
+            let position = layout.object_to_world(Vec2::new(spawn.x, spawn.y), bounds.min);
+            return Some(layout.tile_index(position).as_vec2());
+        }
+    }
+    None
+}
+
+/// Cell layout of the tile layers of `map`, relative to the layer.
+pub fn map_layout(map: &tiled::Map, bounds: &MapBounds) -> TileLayout {
+    let axis = match map.stagger_axis {
+        tiled::StaggerAxis::X => StaggerAxis::X,
+        tiled::StaggerAxis::Y => StaggerAxis::Y,
+    };
+    let index = match map.stagger_index {
+        tiled::StaggerIndex::Odd => StaggerIndex::Odd,
+        tiled::StaggerIndex::Even => StaggerIndex::Even,
+    };
+    let orientation = match map.orientation {
+        tiled::Orientation::Orthogonal => MapOrientation::Orthogonal,
+        tiled::Orientation::Isometric => MapOrientation::Isometric,
+        tiled::Orientation::Staggered => MapOrientation::Staggered { axis, index },
+        tiled::Orientation::Hexagonal => MapOrientation::Hexagonal {
+            axis,
+            index,
+            side_length: map.hex_side_length.max(0) as u32,
+        },
+    };
+    TileLayout::new(
+        orientation,
+        Vec2::new(map.tile_width as f32, map.tile_height as f32),
+        bounds.size,
+    )
+}
+
+/// Animation of `tile` as authored in Tiled, with its frames looked up in `tileset_texture`.
+fn get_animation(tile: &Tile, tileset_texture: &TilesetTexture) -> Option<TileAnimation> {
+    let frames = tile.animation.as_ref()?;
     let tileset_name = &tile.tileset().name;
 
-    if let Some(a) = tile.animation.as_ref().filter(|a| a.len() > 1) {
-        let mut previous = a[0].duration;
-        let mut previous_id = a[0].tile_id;
+    let frames = frames
+        .iter()
+        .map(|frame| {
+            let texture_index = tileset_texture.tile_texture_index(frame.tile_id);
+            if texture_index.is_none() {
+                // Image collection frames of another size live in another texture
+                let id = frame.tile_id;
+                warn!("[{tileset_name} {id}] Frame is not the same size as the animated tile. Animation is not possible");
+            }
+            Some(TileAnimationFrame {
+                texture_index: texture_index?,
+                duration: Duration::from_millis(frame.duration as u64),
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let animation = TileAnimation::new(frames);
+    if animation.is_none() {
+        warn!("[{tileset_name}] Animation is defined, but has no frames to play");
+    }
+    animation
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use bevy::math::{IVec2, UVec2, Vec2};
+    use bevy::prelude::Color;
+    use bevy::tasks::block_on;
+    use bevy_ecs_tilemap::prelude::TilemapTileSize;
+
+    use crate::helpers::coordinate_utils::{MapOrientation, StaggerAxis, StaggerIndex, TileLayout};
+    use crate::levels::WalkableTiles;
 
-        for (i, frame) in (&a[1..]).iter().enumerate() {
-            let current = frame.duration;
-            let id = frame.tile_id;
+    use super::{
+        derive_elevation_tiles, derive_spawn_point, derive_walkable_tiles, load_tmx_map,
+        map_layout, LayerRendering, MapBounds, TilesetTexture,
+    };
 
-            if current != previous {
-                warn!("[{tileset_name} {id}] Frame {i} duration does not match previous. {current} != {previous}");
+    #[test]
+    fn test_walkability_derived_from_properties_and_collision_layer() {
+        let map = tiled::Loader::new()
+            .load_tmx_map("tests/level/walkable.tmx")
+            .unwrap();
+        let walkable = derive_walkable_tiles(&map).unwrap();
+
+        let expected = [[true, true, false], [false, true, true]];
+        for (y, row) in expected.iter().enumerate() {
+            for (x, &is_walkable) in row.iter().enumerate() {
+                assert_eq!(is_walkable, walkable.is_walkable_local(x, y), "({x}, {y})");
             }
+        }
+    }
 
-            if id - 1 != previous_id {
-                warn!("[{tileset_name} {id}] Frame {i} is not right after {previous_id}. Animation is not possible");
-                return None;
+    #[test]
+    fn test_elevation_derived_from_layer_properties() {
+        let map = tiled::Loader::new()
+            .load_tmx_map("tests/level/elevation.tmx")
+            .unwrap();
+        let elevation = derive_elevation_tiles(&map).unwrap();
+
+        let floors = [[0, 0, 1], [0, 0, 1]];
+        for (y, row) in floors.iter().enumerate() {
+            for (x, &floor) in row.iter().enumerate() {
+                assert_eq!(floor, elevation.get(x, y).unwrap().floor, "({x}, {y})");
             }
+        }
+        assert!(elevation.get(1, 1).unwrap().connector);
+        assert!(!elevation.get(2, 1).unwrap().connector);
+    }
+
+    #[test]
+    fn test_game_level_elevation_derived_from_layer_properties() {
+        let map = tiled::Loader::new()
+            .load_tmx_map("assets/levels/level1.tmx")
+            .unwrap();
+        let elevation = derive_elevation_tiles(&map).unwrap();
 
-            previous = current;
-            previous_id = id;
+        // Water, both islands, the bridge between them and the second level of the east one
+        let tiles = [
+            ((0, 0), 0, false),
+            ((8, 8), 1, false),
+            ((22, 10), 1, false),
+            ((15, 9), 1, true),
+            ((26, 6), 2, false),
+        ];
+        for ((x, y), floor, connector) in tiles {
+            let tile = elevation.get(x, y).unwrap();
+            assert_eq!(
+                (floor, connector),
+                (tile.floor, tile.connector),
+                "({x}, {y})"
+            );
         }
+    }
 
-        Some(AnimatedTile {
-            start: a[0].tile_id,
-            end: a[a.len() - 1].tile_id,
-            speed: (a[0].duration as f32) / 100.,
-        })
-    } else {
-        warn!("[{tileset_name}] Animation is defined, but has only 0 or 1 frames");
-        None
+    #[test]
+    fn test_spawn_point_from_object_layer() {
+        let map = tiled::Loader::new()
+            .load_tmx_map("tests/level/objects.tmx")
+            .unwrap();
+        // The rectangle named spawn before the point isn't one
+        assert_eq!(Some(Vec2::new(2., 1.)), derive_spawn_point(&map));
+    }
+
+    #[test]
+    fn test_game_level_walkability_derived_from_collision_layer() {
+        let map = tiled::Loader::new()
+            .load_tmx_map("assets/levels/level1.tmx")
+            .unwrap();
+        // The tiles level1.ccwl.json listed before its map had a collision layer
+        let expected: WalkableTiles = serde_json::from_str(
+            &std::fs::read_to_string("tests/level/level1_walkable.json").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(expected.grid(), derive_walkable_tiles(&map).unwrap().grid());
+    }
+
+    #[test]
+    fn test_infinite_map_bounded_by_chunks() {
+        let map = tiled::Loader::new()
+            .load_tmx_map("tests/level/infinite.tmx")
+            .unwrap();
+        assert_eq!(
+            MapBounds {
+                min: IVec2::new(-16, 0),
+                size: UVec2::new(32, 32),
+            },
+            MapBounds::of(&map)
+        );
+
+        let walkable = derive_walkable_tiles(&map).unwrap();
+        assert_eq!((32, 32), (walkable.grid().x_max(), walkable.grid().y_max()));
+        assert!(!walkable.is_walkable_local(0, 0));
+        assert!(walkable.is_walkable_local(1, 0));
+        assert!(walkable.is_walkable_local(16, 16));
+        // No chunk there
+        assert!(!walkable.is_walkable_local(16, 0));
+
+        assert_eq!(Some(Vec2::new(1., 1.)), derive_spawn_point(&map));
+    }
+
+    #[test]
+    fn test_isometric_spawn_point_projected_on_tiles() {
+        let map = tiled::Loader::new()
+            .load_tmx_map("tests/level/isometric.tmx")
+            .unwrap();
+        assert_eq!(
+            MapOrientation::Isometric,
+            map_layout(&map, &MapBounds::of(&map)).orientation
+        );
+        assert_eq!(Some(Vec2::new(2., 1.)), derive_spawn_point(&map));
+    }
+
+    #[test]
+    fn test_hexagonal_layout_read_from_map() {
+        let map = tiled::Loader::new()
+            .load_tmx_map("tests/level/hexagonal.tmx")
+            .unwrap();
+        assert_eq!(
+            MapOrientation::Hexagonal {
+                axis: StaggerAxis::Y,
+                index: StaggerIndex::Odd,
+                side_length: 14,
+            },
+            map_layout(&map, &MapBounds::of(&map)).orientation
+        );
+        // Close to the center of tile (1, 1), shifted right by half a tile
+        assert_eq!(Some(Vec2::new(1., 1.)), derive_spawn_point(&map));
+    }
+
+    #[test]
+    fn test_layer_rendering_from_attributes_and_properties() {
+        let map = tiled::Loader::new()
+            .load_tmx_map("tests/level/layers.tmx")
+            .unwrap();
+        let rendering: Vec<_> = map
+            .layers()
+            .enumerate()
+            .map(|(index, layer)| LayerRendering::of(&layer, index))
+            .collect();
+
+        assert_eq!(
+            LayerRendering {
+                z: 0.,
+                parallax: Vec2::ONE,
+                visible: true,
+                tint: Color::WHITE,
+                sorted: false,
+            },
+            rendering[0]
+        );
+        assert_eq!(
+            LayerRendering {
+                z: 1.,
+                parallax: Vec2::new(0.5, 0.25),
+                visible: false,
+                tint: Color::rgba_u8(255, 0, 0, 255).with_a(0.5),
+                sorted: false,
+            },
+            rendering[1]
+        );
+        let tint = Color::rgba_u8(255, 255, 255, 128);
+        assert_eq!(
+            LayerRendering {
+                z: 10.,
+                parallax: Vec2::ZERO,
+                visible: true,
+                tint: tint.with_a(tint.a() * 0.25),
+                sorted: false,
+            },
+            rendering[2]
+        );
+        assert!(rendering[3].sorted);
+    }
+
+    #[test]
+    fn test_big_tiles_anchored_at_bottom_of_cell() {
+        let layout = |orientation| TileLayout::new(orientation, Vec2::splat(64.), UVec2::ONE);
+        let tree = TilesetTexture::image_collection(0, TilemapTileSize { x: 128., y: 192. });
+        assert_eq!(
+            Vec2::new(32., 64.),
+            tree.anchor_offset(&layout(MapOrientation::Orthogonal))
+        );
+        assert_eq!(
+            Vec2::new(0., 64.),
+            tree.anchor_offset(&layout(MapOrientation::Isometric))
+        );
+
+        let ground = TilesetTexture::image_collection(0, TilemapTileSize { x: 64., y: 64. });
+        assert_eq!(
+            Vec2::ZERO,
+            ground.anchor_offset(&layout(MapOrientation::Orthogonal))
+        );
+    }
+
+    #[test]
+    fn test_external_tileset_fetched_on_demand() {
+        let path = Path::new("tests/level/external_tileset.tmx");
+        let mut fetched = Vec::new();
+        let map = block_on(load_tmx_map(path, &std::fs::read(path).unwrap(), |path| {
+            fetched.push(path.clone());
+            async move { Ok::<_, anyhow::Error>(std::fs::read(path)?) }
+        }))
+        .unwrap();
+
+        assert_eq!(vec![PathBuf::from("tests/level/terrain.tsx")], fetched);
+        let walkable = derive_walkable_tiles(&map).unwrap();
+        assert!(walkable.is_walkable_local(0, 0));
+        assert!(!walkable.is_walkable_local(1, 0));
+    }
+
+    #[test]
+    fn test_missing_external_tileset_fails() {
+        let path = Path::new("tests/level/external_tileset.tmx");
+        let result = block_on(load_tmx_map(
+            path,
+            &std::fs::read(path).unwrap(),
+            |_| async { Err(anyhow::anyhow!("not found")) },
+        ));
+        assert!(result.is_err());
     }
 }
@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+
+/// World z of the band y-sorted entities and tiles of sorted layers are drawn in, from
+/// `SORTED_Z` to `SORTED_Z + 1`. Layers drawn over characters need a bigger `z` property.
+pub const SORTED_Z: f32 = 100.;
+/// Feet further than this from the world origin share the z of the band edges. Bigger extents
+/// lose precision, feet less than a pixel apart would share their z.
+const SORTED_EXTENT: f32 = 10_000.;
+
+/// Draws the entity behind what has its feet lower on screen and in front of the rest.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct YSort {
+    /// From the entity translation to its feet, which are what gets sorted
+    pub foot_offset: f32,
+}
+
+impl YSort {
+    pub fn new(foot_offset: f32) -> Self {
+        Self { foot_offset }
+    }
+}
+
+/// World z of feet at world y `foot`, lower feet draw in front.
+pub fn sorted_z(foot: f32) -> f32 {
+    SORTED_Z + (0.5 - foot / (2. * SORTED_EXTENT)).clamp(0., 1.)
+}
+
+/// Runs after everything moved, parents are assumed to be neither rotated nor scaled.
+pub fn y_sort(
+    parents: Query<&GlobalTransform>,
+    mut sorted: Query<(&YSort, &mut Transform, Option<&Parent>)>,
+) {
+    for (y_sort, mut transform, parent) in sorted.iter_mut() {
+        let parent_translation = parent
+            .and_then(|parent| parents.get(parent.get()).ok())
+            .map_or(Vec3::ZERO, |parent| parent.translation());
+        let foot = parent_translation.y + transform.translation.y + y_sort.foot_offset;
+        let z = sorted_z(foot) - parent_translation.z;
+        // Avoid change detection re-extracting still entities
+        if transform.translation.z != z {
+            transform.translation.z = z;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sorted_z, SORTED_Z};
+
+    #[test]
+    fn test_lower_feet_draw_in_front() {
+        assert!(sorted_z(-10.) > sorted_z(0.));
+        assert!(sorted_z(0.) > sorted_z(1.));
+        assert!(sorted_z(5000.) > sorted_z(5001.));
+    }
+
+    #[test]
+    fn test_sorted_z_stays_in_band() {
+        for foot in [-1e9, -5000., 0., 5000., 1e9] {
+            let z = sorted_z(foot);
+            assert!((SORTED_Z..=SORTED_Z + 1.).contains(&z), "{foot} -> {z}");
+        }
+    }
+}
@@ -1,26 +1,78 @@
 use bevy::{math::Vec3Swizzles, prelude::*};
 use bevy_asset_loader::loading_state::{LoadingState, LoadingStateAppExt};
 use bevy_common_assets::json::JsonAssetPlugin;
-use bevy_ecs_tilemap::{
-    map::{TilemapGridSize, TilemapSize, TilemapType},
-    tiles::TilePos,
-};
 
-use crate::{helpers::coordinate_utils::CoordinateOps, player::Player};
+use crate::{
+    collision::{Collider, TileCollisionGrid},
+    helpers::{
+        coordinate_utils::TileLayout,
+        tiled::{LayerParallax, RemoveMap, TiledMap},
+    },
+    mover::KinematicSet,
+};
 
-use super::{Level, LevelConfig};
+use super::{
+    elevation::Floor,
+    error_overlay::LevelErrorOverlayPlugin,
+    objects::{apply_pending_prefabs, LevelObjectsPlugin},
+    painter::WalkabilityPainterPlugin,
+    pathfinding::CachedPathfinder,
+    registry::LevelManifest,
+    transition::LevelTransitionPlugin,
+    validation::{
+        send_invalid_level_files, InvalidLevelFile, LevelConfigLoader, RejectedLevelFiles,
+    },
+    walkability_overlay::WalkabilityOverlayPlugin,
+    Level, LevelConfig, LevelError,
+};
 
 pub struct LevelCoordniatorPlugin;
 
 impl Plugin for LevelCoordniatorPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(JsonAssetPlugin::<LevelConfig>::new(&["ccwl.json"]))
+        let rejected = RejectedLevelFiles::default();
+        app.add_asset::<LevelConfig>()
+            .add_asset_loader(LevelConfigLoader::new(rejected.clone()))
+            .insert_resource(rejected)
+            .add_plugins(JsonAssetPlugin::<LevelManifest>::new(&["manifest.json"]))
+            .add_plugins((
+                LevelObjectsPlugin,
+                LevelTransitionPlugin,
+                LevelErrorOverlayPlugin,
+                WalkabilityOverlayPlugin,
+                WalkabilityPainterPlugin,
+            ))
             .add_state::<LevelLoadingStates>()
+            .init_resource::<CachedPathfinder>()
+            .add_event::<LevelError>()
+            .add_event::<InvalidLevelFile>()
+            .add_event::<SnappedToWalkable>()
             .add_loading_state(
                 LoadingState::new(LevelLoadingStates::Loading)
                     .continue_to_state(LevelLoadingStates::Ready),
             )
-            .add_systems(Update, handle_out_of_bounds);
+            .add_systems(
+                Update,
+                init_floors
+                    .after(apply_pending_prefabs)
+                    .before(KinematicSet::Intent),
+            )
+            .add_systems(
+                Update,
+                (handle_out_of_bounds, track_floors)
+                    .chain()
+                    .in_set(KinematicSet::Resolve),
+            )
+            .add_systems(
+                Update,
+                (
+                    reload_level,
+                    validate_level,
+                    send_invalid_level_files,
+                    log_level_errors,
+                )
+                    .chain(),
+            );
     }
 }
 
@@ -31,67 +83,185 @@ pub enum LevelLoadingStates {
     Ready,
 }
 
+/// Re-derives walkability, elevation and spawn point when the map or config is edited while
+/// the game runs. The map's layers are rebuilt by the tiled helper.
+fn reload_level(
+    mut map_events: EventReader<AssetEvent<TiledMap>>,
+    mut config_events: EventReader<AssetEvent<LevelConfig>>,
+    mut levels: Query<(&mut Level, &Handle<TiledMap>)>,
+    level_configs: Res<Assets<LevelConfig>>,
+    maps: Res<Assets<TiledMap>>,
+) {
+    let modified_maps: Vec<_> = map_events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { handle } => Some(handle.clone_weak()),
+            _ => None,
+        })
+        .collect();
+    let modified_configs: Vec<_> = config_events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { handle } => Some(handle.clone_weak()),
+            _ => None,
+        })
+        .collect();
+
+    for (mut level, map_handle) in levels.iter_mut() {
+        if !modified_maps.contains(map_handle) && !modified_configs.contains(&level.cfg) {
+            continue;
+        }
+        let (Some(cfg), Some(map)) = (level_configs.get(&level.cfg), maps.get(map_handle)) else {
+            continue;
+        };
+        info!("Level changed, reloading it");
+        *level = Level::new(level.cfg.clone(), cfg, map);
+    }
+}
+
+/// Runs on spawned and reloaded levels.
+fn validate_level(
+    levels: Query<(&Level, &Handle<TiledMap>), Changed<Level>>,
+    level_configs: Res<Assets<LevelConfig>>,
+    maps: Res<Assets<TiledMap>>,
+    mut errors: EventWriter<LevelError>,
+) {
+    for (level, map_handle) in levels.iter() {
+        let (Some(cfg), Some(map)) = (level_configs.get(&level.cfg), maps.get(map_handle)) else {
+            continue;
+        };
+        errors.send_batch(level.validate(cfg, map));
+    }
+}
+
+fn log_level_errors(
+    mut errors: EventReader<LevelError>,
+    mut invalid_files: EventReader<InvalidLevelFile>,
+) {
+    for e in errors.iter() {
+        error!("Level is misconfigured: {}", e);
+    }
+    for file in invalid_files.iter() {
+        for e in &file.errors {
+            error!("Level file {} is invalid: {}", file.path.display(), e);
+        }
+    }
+}
+
+/// Entity moved back onto the nearest walkable tile by [`handle_out_of_bounds`].
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct SnappedToWalkable {
+    pub entity: Entity,
+    pub from: Vec2,
+    pub to: Vec2,
+}
+
 fn handle_out_of_bounds<'a>(
     level: Query<&Level>,
-    tilemap: Query<(&TilemapGridSize, &TilemapType, &TilemapSize, &Transform), Without<Player>>,
-    mut moving_entities: Query<&mut Transform, (With<Player>, Changed<Transform>)>,
-    level_config_assets: Res<Assets<LevelConfig>>,
+    tilemap: Query<(&TileLayout, &Transform), (Without<Collider>, Without<LayerParallax>)>,
+    mut moving_entities: Query<
+        (Entity, &mut Transform, &Collider, Option<&Floor>),
+        Changed<Transform>,
+    >,
+    mut snaps: EventWriter<SnappedToWalkable>,
 ) {
     level.for_each(|l| {
-        tilemap.for_each(|(grid_size, map_type, map_size, map_transform)| {
-            let cfg = level_config_assets
-                .get(&l.cfg)
-                .expect("LevelConfig not found or unexpectedly unloaded!");
-            moving_entities.for_each_mut(|mut entity_transform| {
-                let entity_world_pos = entity_transform.translation.xy();
-                let coordinate_zero = map_transform.translation.xy().tiled_top_left(map_size, grid_size);
-                let entity_position = entity_world_pos.relative_to(&coordinate_zero).abs();
-                let pos = TilePos::from_world_pos(&entity_position, map_size, grid_size, map_type);
-
-                if pos.is_none() {
-                    warn!(
-                        "Can't get tile for position {:?}. Orig: {:?} {:?}",
-                        entity_position, coordinate_zero, entity_world_pos
-                    );
-                    return;
+        // Every layer shares the map grid, so the first one is enough
+        let Some((layout, map_transform)) = tilemap.iter().next() else {
+            return;
+        };
+        let layout = layout.at(map_transform.translation.xy());
+        let level_tiles = TileCollisionGrid::from_map(l.walkable_tiles.grid(), layout);
+
+        moving_entities.for_each_mut(|(entity, mut entity_transform, collider, floor)| {
+            // Snap the collider itself, not the sprite origin, onto the walkable tile
+            let entity_world_pos = entity_transform.translation.xy() + collider.offset;
+            let tiles = match (&l.elevation, floor) {
+                (Some(elevation), Some(floor)) => {
+                    level_tiles.on_floor(elevation, **floor, entity_world_pos)
                 }
-                let pos = pos.unwrap();
-
-                if !cfg
-                    .walkable_tiles
-                    .is_walkable_local(pos.x as usize, pos.y as usize)
-                {
-                    debug!("Not walkable {:?} {:?}", pos.x, pos.y);
-                    if let Some((x, y)) = cfg
-                        .walkable_tiles
-                        .nearest_walkable_tiles_local((pos.x as usize, pos.y as usize))
-                        .into_iter()
-                        .map(|(x, y)| {
-                            let tile_pos = TilePos::new(x as u32, y as u32);
-                            let (x, y) = tile_pos
-                                .center_in_world(grid_size, map_type)
-                                .copy_signs(&Vec2::new(1., -1.))
-                                .undo_relative(&coordinate_zero)
-                                .into();
-                            (x, y)
-                        })
-                        .min_by(|&a, &b| {
-                            let a_dist = Vec2::from(a).distance_squared(entity_world_pos);
-                            let b_dist = Vec2::from(b).distance_squared(entity_world_pos);
-                            a_dist.total_cmp(&b_dist)
-                        })
-                    {
-                        debug!("Moving to {:?} {:?}", x, y);
-                        entity_transform.translation =
-                            Vec3::new(x, y, entity_transform.translation.z);
-                    } else {
-                        warn!(
-                            "Couldn't find nearest tile from pos: {:#?} to displace from {:#?}",
-                            pos, entity_transform.translation
-                        );
-                    }
+                _ => level_tiles,
+            };
+            if !tiles.collides(collider, entity_transform.translation.xy()) {
+                return;
+            }
+
+            let pos = layout.tile_at(entity_world_pos);
+            if let Some((x, y)) = pos {
+                if tiles.is_walkable(x, y) {
+                    return;
                 }
-            })
+            }
+            debug!("Not walkable {:?}", pos);
+            if let Some((x, y)) = tiles.nearest_walkable_tile(entity_world_pos) {
+                let target = layout.tile_to_world(x, y);
+                debug!("Moving to {:?}", target);
+                snaps.send(SnappedToWalkable {
+                    entity,
+                    from: entity_world_pos,
+                    to: target,
+                });
+                entity_transform.translation =
+                    (target - collider.offset).extend(entity_transform.translation.z);
+            } else {
+                warn!(
+                    "Couldn't find nearest tile from pos: {:#?} to displace from {:#?}",
+                    pos, entity_transform.translation
+                );
+            }
+        })
+    })
+}
+
+/// Puts new entities on the floor of the tile they spawn on, before they first move.
+fn init_floors(
+    level: Query<&Level, Without<RemoveMap>>,
+    tilemap: Query<(&TileLayout, &Transform), (Without<Collider>, Without<LayerParallax>)>,
+    mut entities: Query<(&GlobalTransform, Option<&Collider>, &mut Floor), Added<Floor>>,
+) {
+    let Ok(l) = level.get_single() else {
+        return;
+    };
+    let Some((layout, map_transform)) = tilemap.iter().next() else {
+        return;
+    };
+    let layout = layout.at(map_transform.translation.xy());
+
+    entities.for_each_mut(|(transform, collider, mut floor)| {
+        let offset = collider.map(|collider| collider.offset).unwrap_or_default();
+        if let Some((x, y)) = layout.tile_at(transform.translation().xy() + offset) {
+            *floor = l.floor_at(x, y);
+        }
+    })
+}
+
+fn track_floors(
+    level: Query<&Level>,
+    tilemap: Query<(&TileLayout, &Transform), (Without<Collider>, Without<LayerParallax>)>,
+    mut entities: Query<(&Transform, &Collider, &mut Floor), Changed<Transform>>,
+) {
+    level.for_each(|l| {
+        let Some(elevation) = &l.elevation else {
+            return;
+        };
+        // Every layer shares the map grid, so the first one is enough
+        let Some((layout, map_transform)) = tilemap.iter().next() else {
+            return;
+        };
+        let tiles = TileCollisionGrid::from_map(
+            l.walkable_tiles.grid(),
+            layout.at(map_transform.translation.xy()),
+        );
+
+        entities.for_each_mut(|(transform, collider, mut floor)| {
+            let Some((x, y)) = tiles.tile_at(transform.translation.xy() + collider.offset) else {
+                return;
+            };
+            let next = elevation.floor_after_step(**floor, x, y);
+            if **floor != next {
+                debug!("Floor changed {} -> {} at {:?}", **floor, next, (x, y));
+                **floor = next;
+            }
         })
     })
 }
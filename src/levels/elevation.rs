@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+
+use super::{BitGrid, Grid};
+
+/// Floor the entity is currently walking on. Entities without it ignore elevation.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, Deref, DerefMut)]
+pub struct Floor(pub u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FloorRange {
+    pub min: u8,
+    pub max: u8,
+}
+
+impl FloorRange {
+    pub fn single(floor: u8) -> Self {
+        Self {
+            min: floor,
+            max: floor,
+        }
+    }
+
+    pub fn contains(&self, floor: u8) -> bool {
+        self.min <= floor && floor <= self.max
+    }
+
+    pub fn overlaps(&self, other: &FloorRange) -> bool {
+        self.min <= other.max && other.min <= self.max
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TileElevation {
+    pub floor: u8,
+    /// Stairs, ramps and bridges linking `floor` with the one above
+    pub connector: bool,
+}
+
+impl TileElevation {
+    pub fn floors(&self) -> FloorRange {
+        if self.connector {
+            FloorRange {
+                min: self.floor,
+                max: self.floor.saturating_add(1),
+            }
+        } else {
+            FloorRange::single(self.floor)
+        }
+    }
+}
+
+/// Floor of every tile. Tiles of different floors only connect through connector tiles,
+/// so cliffs between them block movement.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ElevationTiles {
+    value: Grid<TileElevation>,
+}
+
+impl From<Grid<TileElevation>> for ElevationTiles {
+    fn from(value: Grid<TileElevation>) -> Self {
+        Self { value }
+    }
+}
+
+impl ElevationTiles {
+    pub fn get(&self, x: usize, y: usize) -> Option<TileElevation> {
+        self.value.get(x, y).copied()
+    }
+
+    /// Floors reachable by an entity on `floor` standing on tile (x, y).
+    pub fn reachable_floors(&self, floor: u8, x: usize, y: usize) -> FloorRange {
+        match self.get(x, y) {
+            Some(tile) if tile.connector && tile.floors().contains(floor) => tile.floors(),
+            _ => FloorRange::single(floor),
+        }
+    }
+
+    pub fn is_passable(&self, x: usize, y: usize, floors: &FloorRange) -> bool {
+        self.get(x, y)
+            .map(|tile| tile.floors().overlaps(floors))
+            .unwrap_or(false)
+    }
+
+    /// Floor after stepping onto tile (x, y). Connectors keep the floor the entity came from.
+    pub fn floor_after_step(&self, floor: u8, x: usize, y: usize) -> u8 {
+        match self.get(x, y) {
+            Some(tile) if !tile.connector => tile.floor,
+            _ => floor,
+        }
+    }
+
+    /// `walkable` restricted to the tiles passable from `floors`.
+    pub fn mask(&self, walkable: &BitGrid, floors: &FloorRange) -> BitGrid {
+        let mut masked = walkable.clone();
+        walkable.for_each(|x, y, &is_walkable| {
+            if is_walkable && !self.is_passable(x, y, floors) {
+                let _ = masked.set(x, y, false);
+            }
+        });
+        masked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::levels::{BitGrid, Grid};
+
+    use super::{ElevationTiles, FloorRange, TileElevation};
+
+    const GROUND: TileElevation = TileElevation {
+        floor: 0,
+        connector: false,
+    };
+    const STAIRS: TileElevation = TileElevation {
+        floor: 0,
+        connector: true,
+    };
+    const PLATEAU: TileElevation = TileElevation {
+        floor: 1,
+        connector: false,
+    };
+
+    fn elevation() -> ElevationTiles {
+        ElevationTiles::from(Grid::try_from(vec![vec![GROUND, STAIRS, PLATEAU, PLATEAU]]).unwrap())
+    }
+
+    #[test]
+    fn test_cliff_blocks_other_floor() {
+        let elevation = elevation();
+        assert!(elevation.is_passable(0, 0, &FloorRange::single(0)));
+        assert!(!elevation.is_passable(2, 0, &FloorRange::single(0)));
+        assert!(elevation.is_passable(2, 0, &FloorRange::single(1)));
+        assert!(!elevation.is_passable(0, 0, &FloorRange::single(1)));
+    }
+
+    #[test]
+    fn test_connector_reachable_from_both_floors() {
+        let elevation = elevation();
+        assert!(elevation.is_passable(1, 0, &FloorRange::single(0)));
+        assert!(elevation.is_passable(1, 0, &FloorRange::single(1)));
+        assert!(!elevation.is_passable(1, 0, &FloorRange::single(2)));
+    }
+
+    #[test]
+    fn test_standing_on_connector_reaches_both_floors() {
+        let elevation = elevation();
+        let floors = elevation.reachable_floors(0, 1, 0);
+        assert!(elevation.is_passable(0, 0, &floors));
+        assert!(elevation.is_passable(2, 0, &floors));
+        assert_eq!(FloorRange::single(0), elevation.reachable_floors(0, 0, 0));
+    }
+
+    #[test]
+    fn test_floor_changes_only_off_connectors() {
+        let elevation = elevation();
+        assert_eq!(0, elevation.floor_after_step(0, 1, 0));
+        assert_eq!(1, elevation.floor_after_step(0, 2, 0));
+        assert_eq!(0, elevation.floor_after_step(1, 0, 0));
+    }
+
+    #[test]
+    fn test_mask_keeps_only_current_floor() {
+        let elevation = elevation();
+        let walkable = BitGrid::filled(1, 4, true);
+        let masked = elevation.mask(&walkable, &FloorRange::single(0));
+        assert_eq!(vec![vec![true, true, false, false]], Vec::from(masked));
+    }
+}
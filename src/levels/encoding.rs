@@ -0,0 +1,502 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+use super::{BitGrid, Grid, GridError, WalkableTiles};
+
+/// Key of [`crate::levels::LevelConfig::walkable_tiles`] in level files.
+pub const WALKABLE_TILES_KEY: &str = "walkable_tiles";
+
+const WALKABLE: char = '.';
+const BLOCKED: char = '#';
+/// Most tiles a row or column of a level file may have in any encoding, see `ccwl.schema.json`.
+/// Longer text rows are [`EncodingError::RowTooLong`], other sizes [`EncodingError::TooManyTiles`].
+pub const MAX_SIDE: usize = 4096;
+
+/// Walkability as written in level files, every encoding goes row by row from the top left tile.
+/// Text encodings use `.` for walkable tiles and `#` for blocked ones.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum WalkableTilesDto {
+    /// `[[[0, 1], [1, 1]]]` or `{"value": [[0, 1], [1, 1]]}`, 1 is walkable
+    Numbers(NumberRows),
+    /// `{"rows": ["#.", ".."]}`
+    Rows { rows: Vec<String> },
+    /// `{"rle": ["#.", "2."]}`, runs of a tile repeated by the count before it, 1 when missing
+    RunLength { rle: Vec<String> },
+    /// `{"width": 2, "height": 2, "base64": "Dg=="}`, a bit per tile, first tile in the lowest bit
+    Base64 {
+        width: usize,
+        height: usize,
+        base64: String,
+    },
+}
+
+/// Struct rather than variant fields, untagged variants can't be read from sequences.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct NumberRows {
+    pub value: Grid<u8>,
+}
+
+impl WalkableTilesDto {
+    pub fn encoding(&self) -> WalkableEncoding {
+        match self {
+            WalkableTilesDto::Numbers(_) => WalkableEncoding::Numbers,
+            WalkableTilesDto::Rows { .. } => WalkableEncoding::Rows,
+            WalkableTilesDto::RunLength { .. } => WalkableEncoding::RunLength,
+            WalkableTilesDto::Base64 { .. } => WalkableEncoding::Base64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WalkableEncoding {
+    Numbers,
+    #[default]
+    Rows,
+    RunLength,
+    Base64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncodingError {
+    Grid(GridError),
+    InvalidTile { x: usize, y: usize, found: char },
+    MissingRunTile { y: usize },
+    Base64(base64::DecodeError),
+    BitCount { expected: usize, found: usize },
+    TooManyTiles { width: usize, height: usize },
+    RowTooLong { y: usize },
+}
+
+impl Display for EncodingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncodingError::Grid(error) => write!(f, "{}", error),
+            EncodingError::InvalidTile { x, y, found } => {
+                write!(f, "InvalidTile {:?} at x: {} y: {}", found, x, y)
+            }
+            EncodingError::MissingRunTile { y } => write!(f, "MissingRunTile at y: {}", y),
+            EncodingError::Base64(error) => write!(f, "Base64 {}", error),
+            EncodingError::BitCount { expected, found } => {
+                write!(f, "BitCount expected: {} found: {}", expected, found)
+            }
+            EncodingError::TooManyTiles { width, height } => {
+                write!(
+                    f,
+                    "TooManyTiles {}x{} max side: {}",
+                    width, height, MAX_SIDE
+                )
+            }
+            EncodingError::RowTooLong { y } => {
+                write!(f, "RowTooLong at y: {} max: {}", y, MAX_SIDE)
+            }
+        }
+    }
+}
+
+impl From<GridError> for EncodingError {
+    fn from(error: GridError) -> Self {
+        EncodingError::Grid(error)
+    }
+}
+
+impl TryFrom<WalkableTilesDto> for WalkableTiles {
+    type Error = EncodingError;
+
+    fn try_from(dto: WalkableTilesDto) -> Result<Self, Self::Error> {
+        let grid = match dto {
+            WalkableTilesDto::Numbers(NumberRows { value }) => {
+                check_size(value.x_max(), value.y_max())?;
+                BitGrid::from(&value.map(|&i| i == 1))
+            }
+            WalkableTilesDto::Rows { rows } => parse_rows(&rows)?,
+            WalkableTilesDto::RunLength { rle } => {
+                check_row_count(&rle)?;
+                let rows = rle
+                    .iter()
+                    .enumerate()
+                    .map(|(y, row)| expand_runs(y, row))
+                    .collect::<Result<Vec<_>, _>>()?;
+                parse_rows(&rows)?
+            }
+            WalkableTilesDto::Base64 {
+                width,
+                height,
+                base64,
+            } => {
+                check_size(width, height)?;
+                let tiles = width * height;
+                let bytes = STANDARD.decode(base64).map_err(EncodingError::Base64)?;
+                let expected = tiles.div_ceil(8);
+                if bytes.len() != expected {
+                    return Err(EncodingError::BitCount {
+                        expected: expected * 8,
+                        found: bytes.len() * 8,
+                    });
+                }
+                let mut grid = BitGrid::filled(height, width, false);
+                for index in 0..tiles {
+                    if bytes[index / 8] & (1 << (index % 8)) != 0 {
+                        grid.set(index % width, index / width, true)?;
+                    }
+                }
+                grid
+            }
+        };
+        info!("Read WalkableTiles:\n{}", to_rows(&grid).join("\n"));
+
+        Ok(Self::from(grid))
+    }
+}
+
+impl From<WalkableTiles> for WalkableTilesDto {
+    fn from(walkable: WalkableTiles) -> Self {
+        walkable.encode(WalkableEncoding::default())
+    }
+}
+
+impl WalkableTiles {
+    /// What level files store, for tools writing them.
+    pub fn encode(&self, encoding: WalkableEncoding) -> WalkableTilesDto {
+        let grid = self.grid();
+        match encoding {
+            WalkableEncoding::Numbers => WalkableTilesDto::Numbers(NumberRows {
+                value: grid.map(|&walkable| walkable as u8),
+            }),
+            WalkableEncoding::Rows => WalkableTilesDto::Rows {
+                rows: to_rows(grid),
+            },
+            WalkableEncoding::RunLength => WalkableTilesDto::RunLength {
+                rle: to_rows(grid).iter().map(|row| compress_runs(row)).collect(),
+            },
+            WalkableEncoding::Base64 => {
+                let mut bytes = vec![0u8; (grid.x_max() * grid.y_max()).div_ceil(8)];
+                for (index, (_, _, &walkable)) in grid.iter().enumerate() {
+                    if walkable {
+                        bytes[index / 8] |= 1 << (index % 8);
+                    }
+                }
+                WalkableTilesDto::Base64 {
+                    width: grid.x_max(),
+                    height: grid.y_max(),
+                    base64: STANDARD.encode(bytes),
+                }
+            }
+        }
+    }
+}
+
+/// Level config `config` with its walkable tiles replaced, in the encoding the file already used.
+/// Other fields are kept as they were, in the same order.
+pub fn write_walkable_tiles(
+    config: &str,
+    walkable: &WalkableTiles,
+) -> Result<String, serde_json::Error> {
+    let mut json: serde_json::Value = serde_json::from_str(config)?;
+    let encoding = json
+        .get(WALKABLE_TILES_KEY)
+        .and_then(|value| WalkableTilesDto::deserialize(value).ok())
+        .map(|dto| dto.encoding())
+        .unwrap_or_default();
+    let value = serde_json::to_value(walkable.encode(encoding))?;
+    match json.as_object_mut() {
+        Some(fields) => {
+            fields.insert(WALKABLE_TILES_KEY.to_string(), value);
+        }
+        None => return Err(serde::de::Error::custom("level config isn't an object")),
+    }
+
+    let mut written = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    json.serialize(&mut serde_json::Serializer::with_formatter(
+        &mut written,
+        formatter,
+    ))?;
+    written.push(b'\n');
+    Ok(String::from_utf8(written).expect("serde_json writes UTF-8"))
+}
+
+fn to_rows(grid: &BitGrid) -> Vec<String> {
+    (0..grid.y_max())
+        .map(|y| {
+            (0..grid.x_max())
+                .map(|x| match grid.get(x, y) {
+                    Some(true) => WALKABLE,
+                    _ => BLOCKED,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// [`EncodingError::TooManyTiles`] when a side is longer than [`MAX_SIDE`].
+fn check_size(width: usize, height: usize) -> Result<(), EncodingError> {
+    if width > MAX_SIDE || height > MAX_SIDE {
+        return Err(EncodingError::TooManyTiles { width, height });
+    }
+    Ok(())
+}
+
+/// Row count of the text encodings, checked before going through their rows. Rows too long
+/// are left to [`EncodingError::RowTooLong`].
+fn check_row_count(rows: &[String]) -> Result<(), EncodingError> {
+    let width = rows.first().map_or(0, |row| row.chars().count());
+    check_size(width.min(MAX_SIDE), rows.len())
+}
+
+fn parse_rows(rows: &[String]) -> Result<BitGrid, EncodingError> {
+    check_row_count(rows)?;
+    let cells = rows
+        .iter()
+        .enumerate()
+        .map(|(y, row)| {
+            if row.chars().nth(MAX_SIDE).is_some() {
+                return Err(EncodingError::RowTooLong { y });
+            }
+            row.chars()
+                .enumerate()
+                .map(|(x, tile)| match tile {
+                    WALKABLE => Ok(true),
+                    BLOCKED => Ok(false),
+                    found => Err(EncodingError::InvalidTile { x, y, found }),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(BitGrid::try_from(cells)?)
+}
+
+fn compress_runs(row: &str) -> String {
+    let mut compressed = String::new();
+    let mut chars = row.chars().peekable();
+    while let Some(tile) = chars.next() {
+        let mut count = 1;
+        while chars.next_if_eq(&tile).is_some() {
+            count += 1;
+        }
+        if count > 1 {
+            compressed.push_str(&count.to_string());
+        }
+        compressed.push(tile);
+    }
+    compressed
+}
+
+/// Row `y` of [`WalkableTilesDto::RunLength`] back to the text of [`WalkableTilesDto::Rows`].
+fn expand_runs(y: usize, row: &str) -> Result<String, EncodingError> {
+    let mut expanded = String::new();
+    let mut width: usize = 0;
+    let mut count: Option<usize> = None;
+    for tile in row.chars() {
+        match tile.to_digit(10) {
+            Some(digit) => {
+                count = count
+                    .unwrap_or(0)
+                    .checked_mul(10)
+                    .and_then(|count| count.checked_add(digit as usize))
+                    .map(Some)
+                    .ok_or(EncodingError::RowTooLong { y })?;
+            }
+            None => {
+                let run = count.unwrap_or(1);
+                width = width
+                    .checked_add(run)
+                    .filter(|&width| width <= MAX_SIDE)
+                    .ok_or(EncodingError::RowTooLong { y })?;
+                for _ in 0..run {
+                    expanded.push(tile);
+                }
+                count = None;
+            }
+        }
+    }
+    match count {
+        Some(_) => Err(EncodingError::MissingRunTile { y }),
+        None => Ok(expanded),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::levels::{BitGrid, LevelConfig, WalkableTiles};
+
+    use super::{
+        write_walkable_tiles, EncodingError, WalkableEncoding, WalkableTilesDto, MAX_SIDE,
+        WALKABLE_TILES_KEY,
+    };
+
+    fn walkable() -> WalkableTiles {
+        WalkableTiles::from(
+            BitGrid::try_from(vec![
+                vec![false, false, true, false, false],
+                vec![true, true, true, true, false],
+            ])
+            .unwrap(),
+        )
+    }
+
+    fn decode(json: &str) -> Result<WalkableTiles, EncodingError> {
+        WalkableTiles::try_from(serde_json::from_str::<WalkableTilesDto>(json).unwrap())
+    }
+
+    #[test]
+    fn test_every_encoding_reads_the_same_tiles() {
+        let expected = walkable();
+        for json in [
+            "[[[0, 0, 1, 0, 0], [1, 1, 1, 1, 0]]]",
+            r##"{"value": [[0, 0, 1, 0, 0], [1, 1, 1, 1, 0]]}"##,
+            r###"{"rows": ["##.##", "....#"]}"###,
+            r##"{"rle": ["2#.2#", "4.#"]}"##,
+            r##"{"width": 5, "height": 2, "base64": "5AE="}"##,
+        ] {
+            assert_eq!(expected.grid(), decode(json).unwrap().grid(), "{json}");
+        }
+    }
+
+    #[test]
+    fn test_encodings_round_trip() {
+        let expected = walkable();
+        for encoding in [
+            WalkableEncoding::Numbers,
+            WalkableEncoding::Rows,
+            WalkableEncoding::RunLength,
+            WalkableEncoding::Base64,
+        ] {
+            let json = serde_json::to_string(&expected.encode(encoding)).unwrap();
+            assert_eq!(expected.grid(), decode(&json).unwrap().grid(), "{json}");
+        }
+        assert_eq!(
+            r###"{"rows":["##.##","....#"]}"###,
+            serde_json::to_string(&expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_long_runs() {
+        let rows = decode(r##"{"rle": ["12.3#"]}"##).unwrap();
+        assert_eq!(15, rows.grid().x_max());
+        assert_eq!(
+            WalkableTilesDto::RunLength {
+                rle: vec!["12.3#".to_string()]
+            },
+            rows.encode(WalkableEncoding::RunLength)
+        );
+    }
+
+    #[test]
+    fn test_invalid_encodings_fail() {
+        assert!(matches!(
+            decode(r##"{"rows": ["..", ".x"]}"##),
+            Err(EncodingError::InvalidTile {
+                x: 1,
+                y: 1,
+                found: 'x'
+            })
+        ));
+        assert!(matches!(
+            decode(r##"{"rows": ["..", "."]}"##),
+            Err(EncodingError::Grid(_))
+        ));
+        assert!(matches!(
+            decode(r##"{"rle": ["2.", "2"]}"##),
+            Err(EncodingError::MissingRunTile { y: 1 })
+        ));
+        assert!(matches!(
+            decode(r##"{"width": 5, "height": 4, "base64": "5AE="}"##),
+            Err(EncodingError::BitCount {
+                expected: 24,
+                found: 16
+            })
+        ));
+    }
+
+    #[test]
+    fn test_oversized_encodings_fail() {
+        let base64 = format!(r#"{{"width": {}, "height": 2, "base64": ""}}"#, usize::MAX);
+        assert!(matches!(
+            decode(&base64),
+            Err(EncodingError::TooManyTiles { height: 2, .. })
+        ));
+        assert!(matches!(
+            decode(r##"{"width": 5000, "height": 1, "base64": ""}"##),
+            Err(EncodingError::TooManyTiles { .. })
+        ));
+        assert!(matches!(
+            decode(r##"{"rle": ["#", "99999999999999999999999."]}"##),
+            Err(EncodingError::RowTooLong { y: 1 })
+        ));
+        assert!(matches!(
+            decode(r##"{"rle": ["4000.4000#"]}"##),
+            Err(EncodingError::RowTooLong { y: 0 })
+        ));
+
+        let long_row = ".".repeat(MAX_SIDE + 1);
+        assert!(matches!(
+            decode(&format!(r#"{{"rows": ["..", "{long_row}"]}}"#)),
+            Err(EncodingError::RowTooLong { y: 1 })
+        ));
+        let rows = vec!["."; MAX_SIDE + 1];
+        for key in ["rows", "rle"] {
+            let json = serde_json::json!({ key: rows }).to_string();
+            assert!(matches!(
+                decode(&json),
+                Err(EncodingError::TooManyTiles { width: 1, height }) if height == MAX_SIDE + 1
+            ));
+        }
+        let numbers = serde_json::json!([[vec![1; MAX_SIDE + 1]]]).to_string();
+        assert!(matches!(
+            decode(&numbers),
+            Err(EncodingError::TooManyTiles { height: 1, width }) if width == MAX_SIDE + 1
+        ));
+    }
+
+    #[test]
+    fn test_level_files_use_compact_rows() {
+        let walkable = std::fs::read_to_string("tests/level/level1_walkable.json").unwrap();
+        let dto = serde_json::from_str::<WalkableTilesDto>(&walkable).unwrap();
+        assert_eq!(WalkableEncoding::Rows, dto.encoding());
+        let walkable = WalkableTiles::try_from(dto).unwrap();
+        assert_eq!((30, 23), (walkable.grid().x_max(), walkable.grid().y_max()));
+
+        // Game levels overriding the map's walkability too
+        for entry in std::fs::read_dir("assets/levels").unwrap() {
+            let path = entry.unwrap().path();
+            if !path.to_string_lossy().ends_with(".ccwl.json") {
+                continue;
+            }
+            let cfg: serde_json::Value =
+                serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            if let Some(walkable) = cfg.get(WALKABLE_TILES_KEY) {
+                let dto = serde_json::from_value::<WalkableTilesDto>(walkable.clone()).unwrap();
+                assert_eq!(WalkableEncoding::Rows, dto.encoding(), "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn test_writing_keeps_the_config_and_its_encoding() {
+        let config = r##"{"tile_size": 64, "walkable_tiles": {"rle": ["5#"]}, "buildings": {}}"##;
+        let written = write_walkable_tiles(config, &walkable()).unwrap();
+        assert_eq!(
+            r##"{
+    "tile_size": 64,
+    "walkable_tiles": {
+        "rle": [
+            "2#.2#",
+            "4.#"
+        ]
+    },
+    "buildings": {}
+}
+"##,
+            written
+        );
+
+        let written = write_walkable_tiles(r#"{"tile_size": 64}"#, &walkable()).unwrap();
+        let cfg = serde_json::from_str::<LevelConfig>(&written).unwrap();
+        assert_eq!(walkable().grid(), cfg.walkable_tiles.unwrap().grid());
+        assert!(written.contains(r#""rows""#));
+    }
+}
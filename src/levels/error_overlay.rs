@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+
+use super::{validation::InvalidLevelFile, LevelError};
+
+/// Lines kept on screen, older ones are dropped first.
+const MAX_LINES: usize = 12;
+
+/// Shows level errors in game until dismissed with Escape.
+pub struct LevelErrorOverlayPlugin;
+
+impl Plugin for LevelErrorOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelErrorLog>().add_systems(
+            Update,
+            (
+                collect_level_errors,
+                dismiss_level_errors,
+                show_level_errors,
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct LevelErrorLog {
+    lines: Vec<String>,
+}
+
+impl LevelErrorLog {
+    pub fn push(&mut self, line: String) {
+        self.lines.push(line);
+        let overflow = self.lines.len().saturating_sub(MAX_LINES);
+        self.lines.drain(..overflow);
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+}
+
+#[derive(Component)]
+struct LevelErrorOverlay;
+
+fn collect_level_errors(
+    mut errors: EventReader<LevelError>,
+    mut invalid_files: EventReader<InvalidLevelFile>,
+    mut log: ResMut<LevelErrorLog>,
+) {
+    for e in errors.iter() {
+        log.push(format!("Level is misconfigured: {e}"));
+    }
+    for file in invalid_files.iter() {
+        for e in &file.errors {
+            log.push(format!("{}: {e}", file.path.display()));
+        }
+    }
+}
+
+fn dismiss_level_errors(keys: Res<Input<KeyCode>>, mut log: ResMut<LevelErrorLog>) {
+    if keys.just_pressed(KeyCode::Escape) && !log.lines.is_empty() {
+        log.lines.clear();
+    }
+}
+
+fn show_level_errors(
+    mut commands: Commands,
+    log: Res<LevelErrorLog>,
+    overlays: Query<Entity, With<LevelErrorOverlay>>,
+) {
+    if !log.is_changed() {
+        return;
+    }
+    for overlay in overlays.iter() {
+        commands.entity(overlay).despawn_recursive();
+    }
+    if log.lines.is_empty() {
+        return;
+    }
+
+    let text = format!("{}\nEscape to dismiss", log.lines.join("\n"));
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(8.),
+                    top: Val::Px(8.),
+                    padding: UiRect::all(Val::Px(8.)),
+                    ..default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.8).into(),
+                z_index: ZIndex::Global(i32::MAX - 1),
+                ..default()
+            },
+            LevelErrorOverlay,
+        ))
+        .with_children(|overlay| {
+            overlay.spawn(TextBundle::from_section(
+                text,
+                TextStyle {
+                    font_size: 16.,
+                    color: Color::rgb(1., 0.4, 0.4),
+                    ..default()
+                },
+            ));
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LevelErrorLog, MAX_LINES};
+
+    #[test]
+    fn test_error_log_keeps_latest_lines() {
+        let mut log = LevelErrorLog::default();
+        for i in 0..MAX_LINES + 3 {
+            log.push(i.to_string());
+        }
+        assert_eq!(MAX_LINES, log.lines().len());
+        assert_eq!("3", log.lines()[0]);
+    }
+}
@@ -1,14 +1,40 @@
 pub mod coordinator;
+pub mod elevation;
+pub mod encoding;
+pub mod error_overlay;
+pub mod objects;
+pub mod painter;
+pub mod pathfinding;
 
 use bevy::prelude::*;
 use bevy::reflect::{TypePath, TypeUuid};
-use itertools::Itertools;
+use bevy::utils::HashSet;
 use serde::{Deserialize, Serialize};
-use std::fmt::{Debug, Display};
+use std::{
+    borrow::Cow,
+    fmt::{Debug, Display},
+    marker::PhantomData,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-use crate::helpers::tiled::TiledMapBundle;
+use crate::collision::TileCollisionGrid;
+use crate::helpers::{
+    chunks::CHUNK_SIZE,
+    coordinate_utils::TileLayout,
+    tiled::{TiledMap, TiledMapBundle},
+};
 
-pub mod level1;
+use self::{
+    elevation::{ElevationTiles, Floor, FloorRange},
+    encoding::{EncodingError, WalkableTilesDto},
+    pathfinding::{Connectivity, Pathfinder, TileCoord},
+};
+
+pub mod registry;
+pub mod transition;
+pub mod validation;
+pub mod walkability_overlay;
 
 pub type Vec2<T> = Vec<Vec<T>>;
 
@@ -18,25 +44,120 @@ pub struct LevelBundle {
     pub level: Level,
 }
 
-#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+/// Cells stored row by row in `C`, see [`BitGrid`] for bools packed in bits.
+#[derive(Debug, Deserialize, Serialize, Default, Clone, PartialEq)]
 #[serde[try_from="Vec<Vec<T>>", into="Vec<Vec<T>>"]]
-pub struct Grid<T: Debug + Clone> {
-    vec2: Vec2<T>,
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
+pub struct Grid<T: Debug + Clone, C: Cells<T> = Vec<T>> {
+    cells: C,
     y_max: usize,
     x_max: usize,
+    _cell: PhantomData<T>,
+}
+
+/// Walkability sized grid, 64 cells per word.
+pub type BitGrid = Grid<bool, BitCells>;
+
+/// Row by row storage of the cells of a [`Grid`].
+pub trait Cells<T>: Debug + Clone {
+    fn filled(len: usize, value: T) -> Self;
+    fn from_vec(cells: Vec<T>) -> Self;
+    fn get(&self, index: usize) -> Option<&T>;
+    /// Panics when `index` is out of bounds.
+    fn set(&mut self, index: usize, value: T);
+}
+
+impl<T: Debug + Clone> Cells<T> for Vec<T> {
+    fn filled(len: usize, value: T) -> Self {
+        vec![value; len]
+    }
+
+    fn from_vec(cells: Vec<T>) -> Self {
+        cells
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        self.as_slice().get(index)
+    }
+
+    fn set(&mut self, index: usize, value: T) {
+        self[index] = value;
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BitCells {
+    words: Vec<u64>,
+    len: usize,
 }
 
-#[derive(Debug)]
+impl Cells<bool> for BitCells {
+    fn filled(len: usize, value: bool) -> Self {
+        let mut words = vec![if value { u64::MAX } else { 0 }; len.div_ceil(64)];
+        // Bits past the end stay clear so that equal grids compare equal
+        if let (Some(last), 1..) = (words.last_mut(), len % 64) {
+            *last &= (1 << (len % 64)) - 1;
+        }
+        Self { words, len }
+    }
+
+    fn from_vec(cells: Vec<bool>) -> Self {
+        let mut bits = Self::filled(cells.len(), false);
+        for (index, value) in cells.into_iter().enumerate() {
+            bits.set(index, value);
+        }
+        bits
+    }
+
+    fn get(&self, index: usize) -> Option<&bool> {
+        // Bits can't be borrowed, constants can
+        (index < self.len).then(|| {
+            if self.words[index / 64] & (1 << (index % 64)) != 0 {
+                &true
+            } else {
+                &false
+            }
+        })
+    }
+
+    fn set(&mut self, index: usize, value: bool) {
+        assert!(index < self.len, "index {index} out of {} bits", self.len);
+        let bit = 1 << (index % 64);
+        if value {
+            self.words[index / 64] |= bit;
+        } else {
+            self.words[index / 64] &= !bit;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum GridError {
-    OutOfBounds { asked: usize, max: usize },
-    MismatchedWidth { widths: Vec<usize> },
+    OutOfBounds {
+        asked: usize,
+        max: usize,
+    },
+    /// First row whose width differs from the width of row 0
+    MismatchedWidth {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
     NoDataInColumns,
 }
 
 impl Display for GridError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            GridError::MismatchedWidth { widths } => write!(f, "MismatchedWidth {:?}", widths),
+            GridError::MismatchedWidth {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "MismatchedWidth row: {} expected: {} found: {}",
+                row, expected, found
+            ),
             GridError::OutOfBounds { asked, max } => {
                 write!(f, "OutOfBounds asked: {} max: {}", asked, max)
             }
@@ -45,51 +166,164 @@ impl Display for GridError {
     }
 }
 
-impl<T: Debug + Clone> From<Grid<T>> for Vec<Vec<T>> {
-    fn from(value: Grid<T>) -> Self {
-        value.vec2
+#[derive(Event, Debug, Clone, PartialEq)]
+pub enum LevelError {
+    TileSizeMismatch {
+        config: f32,
+        map: bevy::math::Vec2,
+    },
+    SpawnOutOfBounds {
+        spawn_point: bevy::math::Vec2,
+    },
+    SpawnNotWalkable {
+        spawn_point: bevy::math::Vec2,
+    },
+    WalkableSizeMismatch {
+        walkable: UVec2,
+        map: UVec2,
+    },
+    /// Walkable region the spawn tile can't walk to, starting at tile `first` row by row
+    UnreachableTiles {
+        first: UVec2,
+        tiles: usize,
+    },
+    MissingImage {
+        tileset: String,
+        path: PathBuf,
+    },
+    InvalidConfig {
+        message: String,
+    },
+    InvalidMap {
+        message: String,
+    },
+    /// Config not matching `ccwl.schema.json` at JSON pointer `pointer`
+    SchemaViolation {
+        pointer: String,
+        message: String,
+    },
+    InvalidWalkableTiles {
+        error: EncodingError,
+    },
+}
+
+impl Display for LevelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LevelError::TileSizeMismatch { config, map } => {
+                write!(
+                    f,
+                    "TileSizeMismatch config: {} map: {}x{}",
+                    config, map.x, map.y
+                )
+            }
+            LevelError::SpawnOutOfBounds { spawn_point } => {
+                write!(f, "SpawnOutOfBounds {:?}", spawn_point)
+            }
+            LevelError::SpawnNotWalkable { spawn_point } => {
+                write!(f, "SpawnNotWalkable {:?}", spawn_point)
+            }
+            LevelError::WalkableSizeMismatch { walkable, map } => write!(
+                f,
+                "WalkableSizeMismatch walkable: {}x{} map: {}x{}",
+                walkable.x, walkable.y, map.x, map.y
+            ),
+            LevelError::UnreachableTiles { first, tiles } => {
+                write!(f, "UnreachableTiles {} from {:?}", tiles, first)
+            }
+            LevelError::MissingImage { tileset, path } => {
+                write!(
+                    f,
+                    "MissingImage tileset: {} path: {}",
+                    tileset,
+                    path.display()
+                )
+            }
+            LevelError::InvalidConfig { message } => write!(f, "InvalidConfig {}", message),
+            LevelError::InvalidMap { message } => write!(f, "InvalidMap {}", message),
+            LevelError::SchemaViolation { pointer, message } => {
+                write!(f, "SchemaViolation at \"{}\": {}", pointer, message)
+            }
+            LevelError::InvalidWalkableTiles { error } => {
+                write!(f, "InvalidWalkableTiles {}", error)
+            }
+        }
+    }
+}
+
+impl<T: Debug + Clone, C: Cells<T>> From<Grid<T, C>> for Vec<Vec<T>> {
+    fn from(grid: Grid<T, C>) -> Self {
+        (0..grid.y_max)
+            .map(|y| {
+                (0..grid.x_max)
+                    .filter_map(|x| grid.get(x, y).cloned())
+                    .collect()
+            })
+            .collect()
     }
 }
 
-impl<T: Debug + Clone> TryFrom<Vec<Vec<T>>> for Grid<T> {
+impl<T: Debug + Clone, C: Cells<T>> TryFrom<Vec<Vec<T>>> for Grid<T, C> {
     type Error = GridError;
 
     fn try_from(vec2: Vec<Vec<T>>) -> Result<Self, Self::Error> {
         let height = vec2.len();
-        let widths: Vec<usize> = vec2.iter().map(|row| row.len()).dedup().collect();
-
-        if widths.is_empty() {
-            error!("Grid doesn't have any data\n{:#?}", vec2);
-            Err(GridError::NoDataInColumns)
-        } else if widths.len() > 1 {
-            error!("Grid doesn't have constant width\n{:#?}", vec2);
-            Err(GridError::MismatchedWidth { widths })
-        } else {
-            Ok(Self {
-                vec2,
+        let Some(width) = vec2.first().map(Vec::len) else {
+            return Err(GridError::NoDataInColumns);
+        };
+
+        match vec2.iter().position(|row| row.len() != width) {
+            Some(row) => Err(GridError::MismatchedWidth {
+                row,
+                expected: width,
+                found: vec2[row].len(),
+            }),
+            None => Ok(Self {
+                cells: C::from_vec(vec2.into_iter().flatten().collect()),
                 y_max: height,
-                x_max: widths[0],
-            })
+                x_max: width,
+                _cell: PhantomData,
+            }),
+        }
+    }
+}
+
+impl From<&Grid<bool>> for BitGrid {
+    fn from(grid: &Grid<bool>) -> Self {
+        Self {
+            cells: BitCells::from_vec(grid.cells.clone()),
+            y_max: grid.y_max,
+            x_max: grid.x_max,
+            _cell: PhantomData,
         }
     }
 }
 
 impl<T: Debug + Clone> Grid<T> {
     pub fn new(height: usize, width: usize, default: T) -> Self {
-        let mut vec2 = Vec::with_capacity(height);
+        Self::filled(height, width, default)
+    }
 
-        for _ in 0..height {
-            let mut row = Vec::with_capacity(width);
-            for _ in 0..width {
-                row.push(default.clone());
-            }
-            vec2.push(row);
-        }
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut T)> {
+        let x_max = self.x_max;
+        self.cells
+            .iter_mut()
+            .enumerate()
+            .map(move |(index, value)| (index % x_max, index / x_max, value))
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        (0..self.y_max).map(|y| &self.cells[y * self.x_max..(y + 1) * self.x_max])
+    }
+}
 
+impl<T: Debug + Clone, C: Cells<T>> Grid<T, C> {
+    pub fn filled(height: usize, width: usize, value: T) -> Self {
         Self {
-            vec2,
+            cells: C::filled(height * width, value),
             y_max: height,
             x_max: width,
+            _cell: PhantomData,
         }
     }
 
@@ -100,31 +334,30 @@ impl<T: Debug + Clone> Grid<T> {
         self.y_max
     }
 
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        (x < self.x_max && y < self.y_max).then_some(y * self.x_max + x)
+    }
+
     pub fn get(&self, x: usize, y: usize) -> Option<&T> {
-        self.vec2
-            .as_slice()
-            .get(y)
-            .and_then(|row| row.as_slice().get(x))
+        self.index(x, y).and_then(|index| self.cells.get(index))
     }
 
+    /// [`GridError::OutOfBounds`] with the first coordinate not below the width or height,
+    /// leaving the grid untouched, when (x, y) is out of bounds.
     pub fn set(&mut self, x: usize, y: usize, value: T) -> Result<(), GridError> {
-        if y > self.vec2.len() {
+        if y >= self.y_max {
             Err(GridError::OutOfBounds {
                 asked: y,
-                max: self.vec2.len(),
+                max: self.y_max,
+            })
+        } else if x >= self.x_max {
+            Err(GridError::OutOfBounds {
+                asked: x,
+                max: self.x_max,
             })
         } else {
-            let row = &mut self.vec2.as_mut_slice()[y];
-
-            if x > row.len() {
-                Err(GridError::OutOfBounds {
-                    asked: x,
-                    max: row.len(),
-                })
-            } else {
-                row.as_mut_slice()[x] = value;
-                Ok(())
-            }
+            self.cells.set(y * self.x_max + x, value);
+            Ok(())
         }
     }
 
@@ -132,84 +365,476 @@ impl<T: Debug + Clone> Grid<T> {
     where
         F: FnMut(usize, usize, &T) -> (),
     {
-        self.vec2
-            .iter()
+        self.iter().for_each(|(x, y, value)| f(x, y, value))
+    }
+
+    /// Cells with their x and y, row by row.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &T)> {
+        self.positions()
             .enumerate()
-            .for_each(|(y, row)| row.iter().enumerate().for_each(|(x, value)| f(x, y, value)))
+            .filter_map(|(index, (x, y))| Some((x, y, self.cells.get(index)?)))
     }
 
-    pub fn map<R: Debug + Clone>(&self, f: fn(&T) -> R) -> Grid<R> {
+    /// Every (x, y) of the grid, row by row.
+    pub fn positions(&self) -> impl Iterator<Item = TileCoord> {
+        let x_max = self.x_max;
+        (0..self.y_max).flat_map(move |y| (0..x_max).map(move |x| (x, y)))
+    }
+
+    /// Cells next to (x, y) within the grid: sides, then corners with [`Connectivity::Eight`].
+    pub fn neighbors(
+        &self,
+        x: usize,
+        y: usize,
+        connectivity: Connectivity,
+    ) -> impl Iterator<Item = TileCoord> + '_ {
+        let offsets: &[(isize, isize)] = match connectivity {
+            Connectivity::Four => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Connectivity::Eight => &[
+                (0, -1),
+                (-1, 0),
+                (1, 0),
+                (0, 1),
+                (-1, -1),
+                (1, -1),
+                (-1, 1),
+                (1, 1),
+            ],
+        };
+        offsets.iter().filter_map(move |&(dx, dy)| {
+            let x = x.checked_add_signed(dx)?;
+            let y = y.checked_add_signed(dy)?;
+            (x < self.x_max && y < self.y_max).then_some((x, y))
+        })
+    }
+
+    pub fn map<R: Debug + Clone>(&self, mut f: impl FnMut(&T) -> R) -> Grid<R> {
         Grid {
-            vec2: self
-                .vec2
-                .iter()
-                .map(|row| row.iter().map(f).collect())
-                .collect(),
+            cells: self.iter().map(|(_, _, value)| f(value)).collect(),
             x_max: self.x_max,
             y_max: self.y_max,
+            _cell: PhantomData,
         }
     }
 
-    pub fn search_from_pos(&self, x: usize, y: usize, f: fn(&T) -> bool) -> Vec<(usize, usize)> {
-        if self.get(x, y).is_none() {
-            warn!("Out of bounds: x {x} y {y}");
-            vec![]
-        } else {
-            if f(&self.vec2[y][x]) {
-                return vec![(x, y)];
+    /// Cells matching `f` connected to (x, y) through cells matching `f`, (x, y) first. Empty when
+    /// (x, y) doesn't match.
+    pub fn flood_fill(
+        &self,
+        x: usize,
+        y: usize,
+        connectivity: Connectivity,
+        f: impl Fn(&T) -> bool,
+    ) -> Vec<TileCoord> {
+        let mut visited = BitGrid::filled(self.y_max, self.x_max, false);
+        self.fill_region(x, y, connectivity, &f, &mut visited)
+    }
+
+    /// Numbers the connected regions of cells matching `f` from 0, in the order their first cell
+    /// comes row by row. Returns the label of every cell, `None` when it doesn't match, and the
+    /// number of regions.
+    pub fn label_regions(
+        &self,
+        connectivity: Connectivity,
+        f: impl Fn(&T) -> bool,
+    ) -> (Grid<Option<usize>>, usize) {
+        let mut labels = Grid::new(self.y_max, self.x_max, None);
+        let mut visited = BitGrid::filled(self.y_max, self.x_max, false);
+        let mut count = 0;
+        for (x, y) in self.positions() {
+            let region = self.fill_region(x, y, connectivity, &f, &mut visited);
+            if region.is_empty() {
+                continue;
             }
+            for (x, y) in region {
+                labels.cells[y * self.x_max + x] = Some(count);
+            }
+            count += 1;
+        }
+        (labels, count)
+    }
 
-            let mut ring = Vec::new();
-            for d in 1..self.x_max.max(self.y_max) {
-                let l = x.saturating_sub(d);
-                let r = (x + d).min(self.x_max - 1);
-                let u = y.saturating_sub(d);
-                let d = (y + d).min(self.y_max - 1);
-
-                // up & down
-                for x in l..=r {
-                    if let Some(_) = self.get(x, u).filter(|&v| f(v)) {
-                        ring.push((x, u));
-                    } else if let Some(_) = self.get(x, d).filter(|&v| f(v)) {
-                        ring.push((x, d));
-                    }
+    /// `visited` has the size of the grid.
+    fn fill_region(
+        &self,
+        x: usize,
+        y: usize,
+        connectivity: Connectivity,
+        f: &impl Fn(&T) -> bool,
+        visited: &mut BitGrid,
+    ) -> Vec<TileCoord> {
+        if !self.get(x, y).is_some_and(f) || visited.get(x, y) == Some(&true) {
+            return vec![];
+        }
+        visited.cells.set(y * self.x_max + x, true);
+        let mut region = vec![(x, y)];
+        let mut next = 0;
+        while let Some(&(x, y)) = region.get(next) {
+            next += 1;
+            for (x, y) in self.neighbors(x, y, connectivity) {
+                if visited.get(x, y) == Some(&false) && self.get(x, y).is_some_and(f) {
+                    visited.cells.set(y * self.x_max + x, true);
+                    region.push((x, y));
                 }
+            }
+        }
+        region
+    }
 
-                // left & right
-                for y in d - 1..u {
-                    if let Some(_) = self.get(l, y).filter(|&v| f(v)) {
-                        ring.push((l, y));
-                    } else if let Some(_) = self.get(r, y).filter(|&v| f(v)) {
-                        ring.push((r, y));
-                    }
-                }
+    /// Keeps the cells of the top left `width` x `height` corner, new ones are `fill`.
+    pub fn resize(&mut self, height: usize, width: usize, fill: T) {
+        let mut cells = C::filled(height * width, fill);
+        for (x, y, value) in self.iter() {
+            if x < width && y < height {
+                cells.set(y * width + x, value.clone());
+            }
+        }
+        self.cells = cells;
+        self.y_max = height;
+        self.x_max = width;
+    }
+
+    /// Matching cells at the smallest Chebyshev distance from (x, y), i.e. on the first square
+    /// ring around it with any, closest first by Euclidean distance then row by row. Empty when
+    /// (x, y) is out of bounds or nothing matches.
+    pub fn search_from_pos(&self, x: usize, y: usize, f: impl Fn(&T) -> bool) -> Vec<TileCoord> {
+        let mut found = self.nearest(x, y, Metric::Chebyshev, f);
+        found.sort_by_key(|&cell| (Metric::Euclidean.distance((x, y), cell), cell.1, cell.0));
+        found
+    }
+
+    /// Matching cells at the smallest `metric` distance from (x, y), row by row. Empty when
+    /// (x, y) is out of bounds or nothing matches.
+    pub fn nearest(
+        &self,
+        x: usize,
+        y: usize,
+        metric: Metric,
+        f: impl Fn(&T) -> bool,
+    ) -> Vec<TileCoord> {
+        if self.get(x, y).is_none() {
+            warn!("Out of bounds: x {x} y {y}");
+            return vec![];
+        }
 
-                if !ring.is_empty() {
-                    return ring;
+        let mut nearest: Vec<TileCoord> = Vec::new();
+        let mut best = usize::MAX;
+        for ring in 0..self.x_max.max(self.y_max) {
+            // Cells of further rings are at least `ring` away with both metrics
+            if metric.distance((0, 0), (ring, 0)) > best {
+                break;
+            }
+            for cell in self.ring(x, y, ring) {
+                if !self.get(cell.0, cell.1).is_some_and(&f) {
+                    continue;
+                }
+                let distance = metric.distance((x, y), cell);
+                if distance < best {
+                    best = distance;
+                    nearest.clear();
+                }
+                if distance == best {
+                    nearest.push(cell);
                 }
             }
-            ring
+        }
+        nearest.sort_by_key(|&(x, y)| (y, x));
+        nearest
+    }
+
+    /// Cells within the grid at Chebyshev distance `ring` from (x, y), row by row.
+    fn ring(&self, x: usize, y: usize, ring: usize) -> impl Iterator<Item = TileCoord> + '_ {
+        let (x, y, ring) = (x as isize, y as isize, ring as isize);
+        (y - ring..=y + ring)
+            .flat_map(move |ry| {
+                let xs: Vec<isize> = if ry == y - ring || ry == y + ring {
+                    (x - ring..=x + ring).collect()
+                } else {
+                    vec![x - ring, x + ring]
+                };
+                xs.into_iter().map(move |rx| (rx, ry))
+            })
+            .filter_map(|(x, y)| {
+                let (x, y) = (usize::try_from(x).ok()?, usize::try_from(y).ok()?);
+                (x < self.x_max && y < self.y_max).then_some((x, y))
+            })
+    }
+}
+
+/// How far apart grid cells are, for nearest cell searches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Steps when moving diagonally costs as much as moving straight
+    Chebyshev,
+    /// Compared squared, to stay exact
+    Euclidean,
+}
+
+impl Metric {
+    pub fn distance(&self, (ax, ay): TileCoord, (bx, by): TileCoord) -> usize {
+        let (dx, dy) = (ax.abs_diff(bx), ay.abs_diff(by));
+        match self {
+            Metric::Chebyshev => dx.max(dy),
+            Metric::Euclidean => dx * dx + dy * dy,
         }
     }
 }
 
+/// Source of [`Level::generation`], shared by every level so that a new one never reuses the
+/// generation of the level it replaces.
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn next_generation() -> u64 {
+    NEXT_GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
 #[derive(Component)]
 pub struct Level {
     pub cfg: Handle<LevelConfig>,
+    /// Only the chunks loaded around the camera on maps streamed in chunks, see
+    /// [`Level::load_chunk`]
+    pub walkable_tiles: WalkableTiles,
+    /// Walkability of every chunk of a streamed map, loaded or not
+    streamed_tiles: Option<WalkableTiles>,
+    /// Origins of the chunks loaded in `walkable_tiles`
+    loaded_chunks: HashSet<TileCoord>,
+    generation: u64,
+    pub elevation: Option<ElevationTiles>,
+    /// Spawn tile, the map's `spawn` object takes precedence over the config
+    pub spawn_point: bevy::math::Vec2,
+}
+
+impl Level {
+    /// Walkability from the config takes precedence over the one derived from the map. Infinite
+    /// maps start without any chunk loaded.
+    pub fn new(cfg_handle: Handle<LevelConfig>, cfg: &LevelConfig, map: &TiledMap) -> Self {
+        let walkable_tiles = cfg
+            .walkable_tiles
+            .clone()
+            .or_else(|| map.walkable_tiles.clone())
+            .unwrap_or_else(|| {
+                warn!("Neither level config nor map define walkable tiles");
+                WalkableTiles::default()
+            });
+        let (walkable_tiles, streamed_tiles) = if map.map.infinite() {
+            let grid = walkable_tiles.grid();
+            let unloaded = BitGrid::filled(grid.y_max(), grid.x_max(), false);
+            (WalkableTiles::from(unloaded), Some(walkable_tiles))
+        } else {
+            (walkable_tiles, None)
+        };
+
+        Self {
+            cfg: cfg_handle,
+            walkable_tiles,
+            streamed_tiles,
+            loaded_chunks: HashSet::default(),
+            generation: next_generation(),
+            elevation: map.elevation.clone(),
+            spawn_point: map.spawn_point.unwrap_or(cfg.spawn_point),
+        }
+    }
+
+    /// Walkability of the whole level, with the loaded chunks of streamed maps as they are now.
+    pub fn all_walkable_tiles(&self) -> Cow<'_, WalkableTiles> {
+        let Some(streamed) = &self.streamed_tiles else {
+            return Cow::Borrowed(&self.walkable_tiles);
+        };
+        let mut all = streamed.clone();
+        for &origin in &self.loaded_chunks {
+            all.copy_square(&self.walkable_tiles, origin, CHUNK_SIZE as usize);
+        }
+        Cow::Owned(all)
+    }
+
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = &TileCoord> {
+        self.loaded_chunks.iter()
+    }
+
+    /// Changes whenever `walkable_tiles` does, to tell when results computed from them are stale.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// To call after changing `walkable_tiles` from outside of the level.
+    pub fn walkable_tiles_changed(&mut self) {
+        self.generation = next_generation();
+    }
+
+    /// Makes the chunk whose top left tile is `origin` walkable where the map is. False when the
+    /// map isn't streamed or the chunk was already loaded.
+    pub fn load_chunk(&mut self, origin: TileCoord) -> bool {
+        let Some(streamed) = &self.streamed_tiles else {
+            return false;
+        };
+        if !self.loaded_chunks.insert(origin) {
+            return false;
+        }
+        self.walkable_tiles
+            .copy_square(streamed, origin, CHUNK_SIZE as usize);
+        self.walkable_tiles_changed();
+        true
+    }
+
+    /// Blocks the chunk at `origin` again, keeping what was painted on it for when it's loaded
+    /// next. False when it wasn't loaded.
+    pub fn unload_chunk(&mut self, origin: TileCoord) -> bool {
+        let Some(streamed) = &mut self.streamed_tiles else {
+            return false;
+        };
+        if !self.loaded_chunks.remove(&origin) {
+            return false;
+        }
+        streamed.copy_square(&self.walkable_tiles, origin, CHUNK_SIZE as usize);
+        self.walkable_tiles
+            .fill_square(origin, CHUNK_SIZE as usize, false);
+        self.walkable_tiles_changed();
+        true
+    }
+
+    /// Config and map disagreements found once both are loaded.
+    pub fn validate(&self, cfg: &LevelConfig, map: &TiledMap) -> Vec<LevelError> {
+        let map_tile_size =
+            bevy::math::Vec2::new(map.map.tile_width as f32, map.map.tile_height as f32);
+        let mut errors = Vec::new();
+
+        if map_tile_size != bevy::math::Vec2::splat(cfg.tile_size) {
+            errors.push(LevelError::TileSizeMismatch {
+                config: cfg.tile_size,
+                map: map_tile_size,
+            });
+        }
+
+        // Streamed levels put it together from their chunks, so it's only done once
+        let walkable_tiles = self.all_walkable_tiles();
+        let grid = walkable_tiles.grid();
+        let walkable = UVec2::new(grid.x_max() as u32, grid.y_max() as u32);
+        if walkable != map.bounds.size {
+            errors.push(LevelError::WalkableSizeMismatch {
+                walkable,
+                map: map.bounds.size,
+            });
+        }
+        errors.extend(self.validate_spawn_point_on(&walkable_tiles));
+        errors.extend(self.validate_reachability_on(&walkable_tiles));
+        errors
+    }
+
+    pub fn validate_spawn_point(&self) -> Option<LevelError> {
+        self.validate_spawn_point_on(&self.all_walkable_tiles())
+    }
+
+    fn validate_spawn_point_on(&self, walkable_tiles: &WalkableTiles) -> Option<LevelError> {
+        let spawn_point = self.spawn_point;
+        let grid = walkable_tiles.grid();
+        if spawn_point.x < 0.
+            || spawn_point.y < 0.
+            || spawn_point.x as usize >= grid.x_max()
+            || spawn_point.y as usize >= grid.y_max()
+        {
+            Some(LevelError::SpawnOutOfBounds { spawn_point })
+        } else if !walkable_tiles.is_walkable_local(spawn_point.x as usize, spawn_point.y as usize)
+        {
+            Some(LevelError::SpawnNotWalkable { spawn_point })
+        } else {
+            None
+        }
+    }
+
+    /// Walkable regions the spawn tile can't walk to, floors aside.
+    pub fn validate_reachability(&self) -> Vec<LevelError> {
+        self.validate_reachability_on(&self.all_walkable_tiles())
+    }
+
+    fn validate_reachability_on(&self, walkable_tiles: &WalkableTiles) -> Vec<LevelError> {
+        let Some((x, y)) = self.spawn_tile_on(walkable_tiles) else {
+            return vec![];
+        };
+        let (labels, count) = walkable_tiles
+            .grid()
+            .label_regions(Connectivity::Four, |&walkable| walkable);
+        let spawn_region = labels.get(x, y).copied().flatten();
+
+        let mut regions: Vec<Option<(TileCoord, usize)>> = vec![None; count];
+        for (x, y, &label) in labels.iter() {
+            if let Some(label) = label.filter(|&label| Some(label) != spawn_region) {
+                regions[label].get_or_insert(((x, y), 0)).1 += 1;
+            }
+        }
+        regions
+            .into_iter()
+            .flatten()
+            .map(|((x, y), tiles)| LevelError::UnreachableTiles {
+                first: UVec2::new(x as u32, y as u32),
+                tiles,
+            })
+            .collect()
+    }
+
+    /// Spawn point, or the nearest walkable tile when it isn't walkable, loaded or not.
+    pub fn spawn_tile(&self) -> Option<TileCoord> {
+        self.spawn_tile_on(&self.all_walkable_tiles())
+    }
+
+    fn spawn_tile_on(&self, walkable_tiles: &WalkableTiles) -> Option<TileCoord> {
+        let x = self.spawn_point.x.max(0.) as usize;
+        let y = self.spawn_point.y.max(0.) as usize;
+        walkable_tiles
+            .nearest_walkable_tiles_local((x, y))
+            .into_iter()
+            .next()
+    }
+
+    /// Floor an entity placed on tile (x, y) starts on, the lower one on connectors.
+    pub fn floor_at(&self, x: usize, y: usize) -> Floor {
+        let floor = self
+            .elevation
+            .as_ref()
+            .and_then(|elevation| elevation.get(x, y));
+        Floor(floor.map(|tile| tile.floor).unwrap_or_default())
+    }
+
+    /// Walkable tiles as seen by an entity that can reach `floors`.
+    pub fn walkable_tiles_on(&self, floors: &FloorRange) -> WalkableTiles {
+        match &self.elevation {
+            Some(elevation) => {
+                WalkableTiles::from(elevation.mask(self.walkable_tiles.grid(), floors))
+            }
+            None => self.walkable_tiles.clone(),
+        }
+    }
 }
 
 #[derive(Default, TypeUuid, TypePath, Deserialize, Debug)]
 #[uuid = "0b891564-23ca-492a-b03c-816402b496b7"]
 pub struct LevelConfig {
     pub tile_size: f32,
-    pub walkable_tiles: WalkableTiles,
+    /// Overrides walkability derived from the Tiled map
+    #[serde(default)]
+    pub walkable_tiles: Option<WalkableTiles>,
+    /// Spawn tile, counted from the top left corner. The map's `spawn` object overrides it
+    #[serde(default)]
     pub spawn_point: bevy::math::Vec2,
 }
 
-#[derive(Default, Deserialize, Debug)]
-#[serde(from = "WalkableTilesDto")]
+#[derive(Default, Deserialize, Serialize, Debug, Clone)]
+#[serde(try_from = "WalkableTilesDto", into = "WalkableTilesDto")]
 pub struct WalkableTiles {
-    value: Grid<bool>,
+    value: BitGrid,
+}
+
+impl From<BitGrid> for WalkableTiles {
+    fn from(value: BitGrid) -> Self {
+        Self { value }
+    }
+}
+
+impl From<Grid<bool>> for WalkableTiles {
+    fn from(value: Grid<bool>) -> Self {
+        Self {
+            value: BitGrid::from(&value),
+        }
+    }
 }
 
 impl WalkableTiles {
@@ -229,11 +854,17 @@ impl WalkableTiles {
         current_tile: (usize, usize),
     ) -> Vec<(usize, usize)> {
         let (x, y) = current_tile;
-        if self.is_walkable_local(x, y) {
-            vec![current_tile]
-        } else {
-            self.value.search_from_pos(x, y, |&b| b)
-        }
+        self.value.nearest(x, y, Metric::Euclidean, |&b| b)
+    }
+
+    /// Walkable tile whose center is the closest to `world_pos`. Measured in world space, since
+    /// neighbours in the grid aren't equally far apart on isometric and hexagonal maps.
+    pub fn nearest_walkable_tile(
+        &self,
+        layout: &TileLayout,
+        world_pos: bevy::math::Vec2,
+    ) -> Option<TileCoord> {
+        TileCollisionGrid::from_map(&self.value, *layout).nearest_walkable_tile(world_pos)
     }
 
     fn local_to_abs_x(&self, x: usize) -> i32 {
@@ -265,38 +896,110 @@ impl WalkableTiles {
     pub fn is_walkable_local(&self, x: usize, y: usize) -> bool {
         self.value.get(x, y).map(|n| n.to_owned()).unwrap_or(false)
     }
-}
 
-impl From<WalkableTilesDto> for WalkableTiles {
-    fn from(dto: WalkableTilesDto) -> Self {
-        let mut last_y = 0;
-        let mut buf =
-            String::with_capacity(dto.value.y_max * dto.value.x_max * 2 + dto.value.y_max);
-        dto.value.for_each(|_, y, &i| {
-            if y != last_y {
-                last_y = y;
-                buf.push('\n');
+    pub fn set_walkable_local(
+        &mut self,
+        x: usize,
+        y: usize,
+        walkable: bool,
+    ) -> Result<(), GridError> {
+        self.value.set(x, y, walkable)
+    }
+
+    /// Copies the `size` x `size` square whose top left tile is `origin` from `other`, which has
+    /// the same size.
+    pub fn copy_square(&mut self, other: &WalkableTiles, origin: TileCoord, size: usize) {
+        for (x, y) in square(origin, size) {
+            if let Some(&walkable) = other.value.get(x, y) {
+                let _ = self.value.set(x, y, walkable);
             }
-            buf.push((i + b'0') as char);
-        });
-        info!("Read WalkableTiles:\n{}", buf);
+        }
+    }
 
-        Self {
-            value: dto.value.map(|&i| i == 1),
+    pub fn fill_square(&mut self, origin: TileCoord, size: usize, walkable: bool) {
+        for (x, y) in square(origin, size) {
+            let _ = self.value.set(x, y, walkable);
         }
     }
+
+    pub fn grid(&self) -> &BitGrid {
+        &self.value
+    }
+
+    pub fn find_path_local(
+        &self,
+        pathfinder: &Pathfinder,
+        from: TileCoord,
+        to: TileCoord,
+    ) -> Option<Vec<TileCoord>> {
+        pathfinder.find_path(&self.value, from, to)
+    }
 }
 
-#[derive(Deserialize)]
-struct WalkableTilesDto {
-    value: Grid<u8>,
+fn square((x0, y0): TileCoord, size: usize) -> impl Iterator<Item = TileCoord> {
+    (y0..y0 + size).flat_map(move |y| (x0..x0 + size).map(move |x| (x, y)))
+}
+
+/// Grid drawn with one string per row, `.` for walkable tiles and anything else for blocked ones.
+#[cfg(test)]
+pub(crate) fn grid_from(rows: &[&str]) -> BitGrid {
+    BitGrid::try_from(
+        rows.iter()
+            .map(|row| row.chars().map(|c| c == '.').collect::<Vec<_>>())
+            .collect::<Vec<_>>(),
+    )
+    .unwrap()
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::levels::LevelConfig;
+    use bevy::{
+        prelude::{Handle, UVec2, Vec2},
+        utils::HashSet,
+    };
+
+    use crate::{
+        helpers::coordinate_utils::{MapOrientation, TileLayout},
+        levels::LevelConfig,
+    };
+
+    use proptest::prelude::*;
+
+    use super::{
+        elevation::{ElevationTiles, Floor, TileElevation},
+        grid_from,
+        pathfinding::{Connectivity, TileCoord},
+        BitGrid, Grid, GridError, Level, LevelError, Metric, WalkableTiles,
+    };
+
+    fn level_with_spawn(spawn_point: Vec2) -> Level {
+        let mut grid = Grid::new(3, 3, false);
+        grid.set(2, 2, true).unwrap();
+        Level {
+            cfg: Handle::default(),
+            walkable_tiles: WalkableTiles::from(grid),
+            streamed_tiles: None,
+            loaded_chunks: HashSet::default(),
+            generation: 0,
+            elevation: None,
+            spawn_point,
+        }
+    }
 
-    use super::Grid;
+    #[test]
+    fn test_spawn_on_plateau_starts_on_upper_floor() {
+        let floor = |floor, connector| TileElevation { floor, connector };
+        let mut level = level_with_spawn(Vec2::new(2., 2.));
+        let mut elevation = Grid::new(3, 3, floor(0, false));
+        elevation.set(1, 2, floor(0, true)).unwrap();
+        elevation.set(2, 2, floor(1, false)).unwrap();
+        level.elevation = Some(ElevationTiles::from(elevation));
+
+        let (x, y) = level.spawn_tile().unwrap();
+        assert_eq!(Floor(1), level.floor_at(x, y));
+        assert_eq!(Floor(0), level.floor_at(1, 2));
+        assert_eq!(Floor(0), level_with_spawn(Vec2::new(2., 2.)).floor_at(x, y));
+    }
 
     #[test]
     fn test_level_config_loads() {
@@ -319,7 +1022,7 @@ mod tests {
         grid.set(21, 11, true).unwrap();
         grid.set(23, 10, true).unwrap();
         assert_eq!(
-            vec!((23, 11), (22, 11), (21, 11)),
+            vec!((22, 11), (21, 11), (23, 11)),
             grid.search_from_pos(22, 12, |&b| b)
         )
     }
@@ -330,4 +1033,355 @@ mod tests {
         grid.set(21, 3, true).unwrap();
         assert_eq!(vec!((21, 3)), grid.search_from_pos(21, 2, |&b| b))
     }
+
+    #[test]
+    fn test_spiral_finds_sides_of_ring() {
+        let mut grid = Grid::new(10, 10, false);
+        grid.set(3, 5, true).unwrap();
+        grid.set(7, 4, true).unwrap();
+        assert_eq!(vec!((3, 5), (7, 4)), grid.search_from_pos(5, 5, |&b| b))
+    }
+
+    #[test]
+    fn test_nearest_depends_on_metric() {
+        let mut grid = Grid::new(10, 10, false);
+        grid.set(3, 3, true).unwrap();
+        grid.set(4, 0, true).unwrap();
+        assert_eq!(vec!((3, 3)), grid.nearest(0, 0, Metric::Chebyshev, |&b| b));
+        assert_eq!(vec!((4, 0)), grid.nearest(0, 0, Metric::Euclidean, |&b| b));
+        assert!(grid.nearest(10, 0, Metric::Euclidean, |&b| b).is_empty());
+    }
+
+    #[test]
+    fn test_set_out_of_bounds_fails() {
+        let mut grid = Grid::new(2, 3, 0);
+        assert!(matches!(
+            grid.set(3, 0, 1),
+            Err(GridError::OutOfBounds { asked: 3, max: 3 })
+        ));
+        assert!(matches!(
+            grid.set(0, 2, 1),
+            Err(GridError::OutOfBounds { asked: 2, max: 2 })
+        ));
+        assert!(grid.set(2, 1, 1).is_ok());
+        assert_eq!(vec![vec![0, 0, 0], vec![0, 0, 1]], Vec::from(grid));
+    }
+
+    /// Grid with a few matching cells and a position in it.
+    fn sparse_grid() -> impl Strategy<Value = (Grid<bool>, TileCoord)> {
+        (1..12usize, 1..12usize)
+            .prop_flat_map(|(width, height)| {
+                (
+                    prop::collection::vec(prop::bool::weighted(0.1), width * height),
+                    Just(width),
+                    0..width,
+                    0..height,
+                )
+            })
+            .prop_map(|(cells, width, x, y)| {
+                let rows: Vec<_> = cells.chunks(width).map(<[bool]>::to_vec).collect();
+                (Grid::try_from(rows).unwrap(), (x, y))
+            })
+    }
+
+    fn brute_force_nearest(grid: &Grid<bool>, from: TileCoord, metric: Metric) -> Vec<TileCoord> {
+        let walkable: Vec<TileCoord> = grid
+            .iter()
+            .filter(|(_, _, &b)| b)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        let best = walkable
+            .iter()
+            .map(|&cell| metric.distance(from, cell))
+            .min();
+        walkable
+            .into_iter()
+            .filter(|&cell| Some(metric.distance(from, cell)) == best)
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn test_nearest_matches_brute_force((grid, (x, y)) in sparse_grid()) {
+            for metric in [Metric::Chebyshev, Metric::Euclidean] {
+                prop_assert_eq!(
+                    brute_force_nearest(&grid, (x, y), metric),
+                    grid.nearest(x, y, metric, |&b| b)
+                );
+            }
+        }
+
+        #[test]
+        fn test_spiral_search_finds_chebyshev_nearest_closest_first(
+            (grid, (x, y)) in sparse_grid()
+        ) {
+            let found = grid.search_from_pos(x, y, |&b| b);
+            let mut sorted = found.clone();
+            sorted.sort_by_key(|&(x, y)| (y, x));
+            prop_assert_eq!(brute_force_nearest(&grid, (x, y), Metric::Chebyshev), sorted);
+            let euclidean = |cell| Metric::Euclidean.distance((x, y), cell);
+            prop_assert!(found.windows(2).all(|pair| euclidean(pair[0]) <= euclidean(pair[1])));
+        }
+
+        #[test]
+        fn test_bit_grid_behaves_like_bool_grid(
+            (grid, (x, y)) in sparse_grid(),
+            value in any::<bool>(),
+        ) {
+            let mut bits = BitGrid::from(&grid);
+            let mut bools = grid;
+            prop_assert_eq!(bits.set(x, y, value).is_ok(), bools.set(x, y, value).is_ok());
+            prop_assert!(bools.iter().eq(bits.iter()));
+            prop_assert_eq!(bools.get(bools.x_max(), y), bits.get(bits.x_max(), y));
+            prop_assert_eq!(
+                bools.nearest(x, y, Metric::Euclidean, |&b| b),
+                bits.nearest(x, y, Metric::Euclidean, |&b| b)
+            );
+            prop_assert_eq!(
+                serde_json::to_string(&bools).unwrap(),
+                serde_json::to_string(&bits).unwrap()
+            );
+        }
+
+        #[test]
+        fn test_set_within_bounds_only(
+            (height, width) in (1..8usize, 1..8usize),
+            (x, y) in (0..10usize, 0..10usize),
+        ) {
+            let mut grid = Grid::new(height, width, false);
+            let set = grid.set(x, y, true);
+            prop_assert_eq!(x < width && y < height, set.is_ok());
+            prop_assert_eq!(set.is_ok(), grid.iter().any(|(_, _, &b)| b));
+        }
+    }
+
+    #[test]
+    fn test_bit_grid_keeps_serde_format() {
+        let json = "[[true,false,true],[false,false,true]]";
+        let bits: BitGrid = serde_json::from_str(json).unwrap();
+        assert_eq!(Some(&true), bits.get(2, 1));
+        assert_eq!(Some(&false), bits.get(1, 1));
+        assert_eq!(None, bits.get(3, 0));
+        assert_eq!(json, serde_json::to_string(&bits).unwrap());
+    }
+
+    #[test]
+    fn test_bit_grid_spans_words() {
+        let mut bits = BitGrid::filled(3, 50, true);
+        bits.set(13, 1, false).unwrap();
+        assert_eq!(149, bits.iter().filter(|(_, _, &b)| b).count());
+        assert_eq!(Some(&false), bits.get(13, 1));
+
+        // Cells dropped by a resize don't come back when growing again
+        bits.resize(1, 10, false);
+        bits.resize(3, 50, false);
+        assert!(bits.iter().all(|(x, y, &b)| b == (y == 0 && x < 10)));
+    }
+
+    #[test]
+    fn test_grid_iterators_go_row_by_row() {
+        let mut grid = Grid::<_>::try_from(vec![vec![1, 2, 3], vec![4, 5, 6]]).unwrap();
+        assert_eq!(
+            vec![(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)],
+            grid.positions().collect::<Vec<_>>()
+        );
+        assert_eq!(Some((1, 1, &5)), grid.iter().nth(4));
+
+        grid.iter_mut()
+            .for_each(|(x, y, value)| *value += 10 * (x + y));
+        assert_eq!(
+            vec![&[1, 12, 23][..], &[14, 25, 36][..]],
+            grid.rows().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_grid_neighbors_stay_in_bounds() {
+        let grid = Grid::new(3, 3, false);
+        let neighbors = |x, y, connectivity| grid.neighbors(x, y, connectivity).collect::<Vec<_>>();
+
+        assert_eq!(vec![(1, 0), (0, 1)], neighbors(0, 0, Connectivity::Four));
+        assert_eq!(
+            vec![(1, 0), (0, 1), (1, 1)],
+            neighbors(0, 0, Connectivity::Eight)
+        );
+        assert_eq!(4, neighbors(1, 1, Connectivity::Four).len());
+        assert_eq!(8, neighbors(1, 1, Connectivity::Eight).len());
+        assert_eq!(vec![(2, 1), (1, 2)], neighbors(2, 2, Connectivity::Four));
+    }
+
+    #[test]
+    fn test_map_and_search_take_closures() {
+        let grid = Grid::<_>::try_from(vec![vec![1, 2], vec![3, 4]]).unwrap();
+        let threshold = 3;
+        let above = grid.map(|&value| value >= threshold);
+        assert_eq!(Some(&false), above.get(1, 0));
+        assert_eq!(Some(&true), above.get(0, 1));
+        assert_eq!(vec![(1, 1)], grid.search_from_pos(1, 1, |&v| v > threshold));
+    }
+
+    #[test]
+    fn test_flood_fill_follows_connectivity() {
+        let grid = grid_from(&[
+            "..##", //
+            "#.##", //
+            "##.#", //
+            "###.",
+        ]);
+
+        assert_eq!(
+            vec![(0, 0), (1, 0), (1, 1)],
+            grid.flood_fill(0, 0, Connectivity::Four, |&b| b)
+        );
+        assert_eq!(
+            vec![(0, 0), (1, 0), (1, 1), (2, 2), (3, 3)],
+            grid.flood_fill(0, 0, Connectivity::Eight, |&b| b)
+        );
+        assert!(grid
+            .flood_fill(3, 0, Connectivity::Eight, |&b| b)
+            .is_empty());
+        assert!(grid
+            .flood_fill(9, 9, Connectivity::Eight, |&b| b)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_label_regions_in_row_order() {
+        let grid = grid_from(&[
+            ".##.", //
+            ".##.", //
+            "##.#", //
+            "..##",
+        ]);
+
+        let (labels, count) = grid.label_regions(Connectivity::Four, |&b| b);
+        assert_eq!(4, count);
+        let labels: Vec<Vec<_>> = labels.into();
+        assert_eq!(
+            vec![
+                vec![Some(0), None, None, Some(1)],
+                vec![Some(0), None, None, Some(1)],
+                vec![None, None, Some(2), None],
+                vec![Some(3), Some(3), None, None],
+            ],
+            labels
+        );
+
+        assert_eq!(2, grid.label_regions(Connectivity::Eight, |&b| b).1);
+        // The bottom right corner is cut off
+        assert_eq!(2, grid.label_regions(Connectivity::Four, |&b| !b).1);
+    }
+
+    #[test]
+    fn test_resize_keeps_top_left() {
+        let mut grid = Grid::<_>::try_from(vec![vec![1, 2], vec![3, 4]]).unwrap();
+        grid.resize(3, 1, 0);
+        assert_eq!((1, 3), (grid.x_max(), grid.y_max()));
+        assert_eq!(vec![vec![1], vec![3], vec![0]], Vec::from(grid.clone()));
+
+        grid.resize(1, 3, 9);
+        assert_eq!(vec![vec![1, 9, 9]], Vec::from(grid));
+    }
+
+    #[test]
+    fn test_spawn_point_validation() {
+        assert_eq!(
+            None,
+            level_with_spawn(Vec2::new(2., 2.)).validate_spawn_point()
+        );
+        assert_eq!(
+            Some(LevelError::SpawnNotWalkable {
+                spawn_point: Vec2::new(1., 1.)
+            }),
+            level_with_spawn(Vec2::new(1., 1.)).validate_spawn_point()
+        );
+        assert_eq!(
+            Some(LevelError::SpawnOutOfBounds {
+                spawn_point: Vec2::new(3., 0.)
+            }),
+            level_with_spawn(Vec2::new(3., 0.)).validate_spawn_point()
+        );
+    }
+
+    #[test]
+    fn test_spawn_tile_falls_back_to_nearest_walkable() {
+        assert_eq!(
+            Some((2, 2)),
+            level_with_spawn(Vec2::new(2., 2.)).spawn_tile()
+        );
+        assert_eq!(
+            Some((2, 2)),
+            level_with_spawn(Vec2::new(1., 1.)).spawn_tile()
+        );
+    }
+
+    #[test]
+    fn test_unloaded_chunks_keep_their_paint() {
+        let mut streamed = BitGrid::filled(32, 32, false);
+        streamed.set(1, 1, true).unwrap();
+        streamed.set(17, 1, true).unwrap();
+        let mut level = Level {
+            walkable_tiles: WalkableTiles::from(BitGrid::filled(32, 32, false)),
+            streamed_tiles: Some(WalkableTiles::from(streamed)),
+            ..level_with_spawn(Vec2::new(1., 1.))
+        };
+        assert!(level.load_chunk((0, 0)));
+        let generation = level.generation();
+        assert!(!level.load_chunk((0, 0)));
+        assert_eq!(generation, level.generation());
+        assert!(level.walkable_tiles.is_walkable_local(1, 1));
+        assert!(!level.walkable_tiles.is_walkable_local(17, 1));
+        assert_eq!(Some((1, 1)), level.spawn_tile());
+
+        level.walkable_tiles.set_walkable_local(2, 1, true).unwrap();
+        assert!(level.all_walkable_tiles().is_walkable_local(2, 1));
+        assert!(level.unload_chunk((0, 0)));
+        assert_ne!(generation, level.generation());
+        assert!(!level.walkable_tiles.is_walkable_local(1, 1));
+        assert!(level.load_chunk((0, 0)));
+        assert!(level.walkable_tiles.is_walkable_local(2, 1));
+    }
+
+    #[test]
+    fn test_regions_out_of_reach_of_spawn() {
+        let level = Level {
+            walkable_tiles: WalkableTiles::from(grid_from(&["..#..", "#.#..", "##.##"])),
+            ..level_with_spawn(Vec2::new(4., 0.))
+        };
+        assert_eq!(
+            vec![
+                LevelError::UnreachableTiles {
+                    first: UVec2::new(0, 0),
+                    tiles: 3,
+                },
+                LevelError::UnreachableTiles {
+                    first: UVec2::new(2, 2),
+                    tiles: 1,
+                },
+            ],
+            level.validate_reachability()
+        );
+        assert!(level_with_spawn(Vec2::ZERO)
+            .validate_reachability()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_nearest_walkable_tile_measured_in_world_space() {
+        let mut grid = Grid::new(4, 4, false);
+        grid.set(2, 0, true).unwrap();
+        grid.set(3, 2, true).unwrap();
+        let walkable_tiles = WalkableTiles::from(grid);
+        let layout = TileLayout::new(
+            MapOrientation::Isometric,
+            Vec2::new(64., 32.),
+            UVec2::new(4, 4),
+        );
+
+        // (2, 0) is the closer one in the grid, but straight right of (1, 1) in the world
+        assert_eq!(
+            Some((3, 2)),
+            walkable_tiles.nearest_walkable_tile(&layout, layout.tile_to_world(1, 1))
+        );
+    }
 }
@@ -0,0 +1,248 @@
+use bevy::{math::Vec3Swizzles, prelude::*, utils::HashMap, utils::HashSet};
+use tiled::{ObjectShape, Properties, PropertyValue};
+
+use crate::collision::Collider;
+
+/// Name of the point object replacing `LevelConfig::spawn_point`.
+pub const SPAWN_OBJECT: &str = "spawn";
+
+/// Other shapes named [`SPAWN_OBJECT`] are spawned like any other object.
+pub fn is_spawn_point(object: &tiled::ObjectData) -> bool {
+    matches!(object.shape, ObjectShape::Point(..)) && object.name == SPAWN_OBJECT
+}
+
+/// Applies a prefab onto the spawned object entity. Returns `false` when it can't be applied yet,
+/// e.g. its assets are still loading, and should be retried.
+pub type Prefab = fn(&mut World, Entity) -> bool;
+
+#[derive(Resource, Default)]
+pub struct PrefabRegistry {
+    prefabs: HashMap<String, Prefab>,
+}
+
+impl PrefabRegistry {
+    pub fn register(&mut self, user_type: &str, prefab: Prefab) {
+        self.prefabs.insert(user_type.to_string(), prefab);
+    }
+
+    pub fn get(&self, user_type: &str) -> Option<Prefab> {
+        self.prefabs.get(user_type).copied()
+    }
+}
+
+pub trait PrefabAppExt {
+    /// Objects with Tiled class/type `user_type` get `prefab` applied once spawned.
+    fn register_prefab(&mut self, user_type: &str, prefab: Prefab) -> &mut Self;
+}
+
+impl PrefabAppExt for App {
+    fn register_prefab(&mut self, user_type: &str, prefab: Prefab) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(PrefabRegistry::default)
+            .register(user_type, prefab);
+        self
+    }
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct MapObject {
+    pub id: u32,
+    pub name: String,
+    pub user_type: String,
+}
+
+/// Custom properties of the Tiled object.
+#[derive(Component, Debug, Clone, Default, Deref)]
+pub struct ObjectProperties(pub Properties);
+
+impl ObjectProperties {
+    pub fn bool(&self, name: &str) -> Option<bool> {
+        match self.0.get(name) {
+            Some(PropertyValue::BoolValue(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn int(&self, name: &str) -> Option<i32> {
+        match self.0.get(name) {
+            Some(PropertyValue::IntValue(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn float(&self, name: &str) -> Option<f32> {
+        match self.0.get(name) {
+            Some(PropertyValue::FloatValue(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn string(&self, name: &str) -> Option<&str> {
+        match self.0.get(name) {
+            Some(PropertyValue::StringValue(value)) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Component, Debug, Default)]
+pub struct SpawnPoint;
+
+#[derive(Component, Debug, Clone)]
+struct PendingPrefab(String);
+
+/// Axis aligned area emitting [`TriggerEvent`]s when colliders enter or leave it.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct TriggerVolume {
+    pub half_extents: Vec2,
+    /// From the object origin (Tiled's top left corner) to the center of the area
+    pub offset: Vec2,
+}
+
+impl TriggerVolume {
+    pub fn contains(&self, origin: Vec2, point: Vec2) -> bool {
+        let delta = (point - (origin + self.offset)).abs();
+        delta.x <= self.half_extents.x && delta.y <= self.half_extents.y
+    }
+}
+
+#[derive(Component, Debug, Default)]
+pub struct TriggerOccupants(HashSet<Entity>);
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerEvent {
+    Entered { trigger: Entity, entity: Entity },
+    Exited { trigger: Entity, entity: Entity },
+}
+
+pub struct LevelObjectsPlugin;
+
+impl Plugin for LevelObjectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PrefabRegistry>()
+            .add_event::<TriggerEvent>()
+            .add_systems(Update, (apply_pending_prefabs, detect_triggers));
+    }
+}
+
+/// Spawns every object of `objects` as a child of `layer`. `place` turns Tiled object
+/// coordinates into positions relative to the layer, since they depend on the map orientation.
+pub fn spawn_object_layer(
+    commands: &mut Commands,
+    layer: Entity,
+    objects: &tiled::ObjectLayer,
+    place: impl Fn(Vec2) -> Vec2,
+) {
+    commands.entity(layer).with_children(|parent| {
+        for object in objects.objects() {
+            let mut entity = parent.spawn((
+                Name::new(object.name.clone()),
+                MapObject {
+                    id: object.id(),
+                    name: object.name.clone(),
+                    user_type: object.user_type.clone(),
+                },
+                ObjectProperties(object.properties.clone()),
+                SpatialBundle::from_transform(Transform::from_translation(
+                    place(Vec2::new(object.x, object.y)).extend(0.),
+                )),
+            ));
+
+            match object.shape {
+                _ if is_spawn_point(&object) => {
+                    entity.insert(SpawnPoint);
+                }
+                ObjectShape::Rect { width, height }
+                    if object.user_type.is_empty() && object.tile_data().is_none() =>
+                {
+                    entity.insert((
+                        TriggerVolume {
+                            half_extents: Vec2::new(width, height) / 2.,
+                            offset: Vec2::new(width, -height) / 2.,
+                        },
+                        TriggerOccupants::default(),
+                    ));
+                }
+                _ => {}
+            }
+
+            if !object.user_type.is_empty() {
+                entity.insert(PendingPrefab(object.user_type.clone()));
+            }
+        }
+    });
+}
+
+pub(super) fn apply_pending_prefabs(world: &mut World) {
+    let pending: Vec<(Entity, String)> = world
+        .query::<(Entity, &PendingPrefab)>()
+        .iter(world)
+        .map(|(entity, prefab)| (entity, prefab.0.clone()))
+        .collect();
+
+    for (entity, user_type) in pending {
+        let Some(prefab) = world.resource::<PrefabRegistry>().get(&user_type) else {
+            warn!("No prefab registered for object type \"{user_type}\"");
+            world.entity_mut(entity).remove::<PendingPrefab>();
+            continue;
+        };
+
+        if prefab(world, entity) {
+            world.entity_mut(entity).remove::<PendingPrefab>();
+        }
+    }
+}
+
+fn detect_triggers(
+    mut triggers: Query<(
+        Entity,
+        &TriggerVolume,
+        &GlobalTransform,
+        &mut TriggerOccupants,
+    )>,
+    colliders: Query<(Entity, &GlobalTransform, &Collider)>,
+    mut events: EventWriter<TriggerEvent>,
+) {
+    for (trigger, volume, trigger_transform, mut occupants) in triggers.iter_mut() {
+        let origin = trigger_transform.translation().xy();
+        let inside: HashSet<Entity> = colliders
+            .iter()
+            .filter(|(_, transform, collider)| {
+                volume.contains(origin, transform.translation().xy() + collider.offset)
+            })
+            .map(|(entity, _, _)| entity)
+            .collect();
+
+        for &entity in inside.difference(&occupants.0) {
+            events.send(TriggerEvent::Entered { trigger, entity });
+        }
+        for &entity in occupants.0.difference(&inside) {
+            events.send(TriggerEvent::Exited { trigger, entity });
+        }
+
+        if occupants.0 != inside {
+            occupants.0 = inside;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use super::TriggerVolume;
+
+    #[test]
+    fn test_trigger_volume_extends_down_right_from_origin() {
+        let volume = TriggerVolume {
+            half_extents: Vec2::new(32., 16.),
+            offset: Vec2::new(32., -16.),
+        };
+        let origin = Vec2::new(100., 100.);
+        assert!(volume.contains(origin, Vec2::new(101., 99.)));
+        assert!(volume.contains(origin, Vec2::new(164., 68.)));
+        assert!(!volume.contains(origin, Vec2::new(99., 99.)));
+        assert!(!volume.contains(origin, Vec2::new(120., 101.)));
+        assert!(!volume.contains(origin, Vec2::new(120., 67.)));
+    }
+}
@@ -0,0 +1,319 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{asset::FileAssetIo, math::Vec3Swizzles, prelude::*, window::PrimaryWindow};
+
+use crate::{
+    collision::Collider,
+    helpers::{coordinate_utils::TileLayout, tiled::LayerParallax},
+};
+
+use super::{
+    encoding::write_walkable_tiles, pathfinding::TileCoord,
+    walkability_overlay::WalkabilityOverlay, Level, WalkableTiles,
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F4;
+const UNDO_KEY: KeyCode = KeyCode::Z;
+const REDO_KEY: KeyCode = KeyCode::Y;
+const SAVE_KEY: KeyCode = KeyCode::S;
+
+/// Editor mode toggled with F4, where clicking or dragging on tiles flips their walkability.
+/// Ctrl+Z undoes a stroke, Ctrl+Y or Ctrl+Shift+Z redoes it and Ctrl+S saves the level config.
+pub struct WalkabilityPainterPlugin;
+
+impl Plugin for WalkabilityPainterPlugin {
+    fn build(&self, app: &mut App) {
+        // Level files are saved back in the folder the asset server loads them from
+        let asset_folder = app.get_added_plugins::<AssetPlugin>().first().map_or_else(
+            || AssetPlugin::default().asset_folder,
+            |plugin| plugin.asset_folder.clone(),
+        );
+        app.insert_resource(WalkabilityPainter {
+            asset_folder: FileAssetIo::get_base_path().join(asset_folder),
+            ..default()
+        })
+        .add_systems(
+            Update,
+            (
+                toggle_painter,
+                (paint_tiles, undo_strokes, save_walkable_tiles).run_if(painting),
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct WalkabilityPainter {
+    pub enabled: bool,
+    history: PaintHistory,
+    asset_folder: PathBuf,
+}
+
+/// Run condition for systems that mustn't react to input while painting, such as player moves.
+pub fn painting(painter: Option<Res<WalkabilityPainter>>) -> bool {
+    painter.is_some_and(|p| p.enabled)
+}
+
+/// Tiles flipped by one click or drag, all to `walkable`.
+#[derive(Debug, Clone, PartialEq)]
+struct Stroke {
+    walkable: bool,
+    tiles: Vec<TileCoord>,
+}
+
+/// Strokes painted on walkable tiles, to undo and redo them.
+#[derive(Debug, Default)]
+pub struct PaintHistory {
+    done: Vec<Stroke>,
+    undone: Vec<Stroke>,
+    stroke: Option<Stroke>,
+}
+
+impl PaintHistory {
+    /// Flips `tile` when it starts a stroke, the next tiles of the stroke take the same value.
+    /// False when the tile didn't change.
+    pub fn paint(&mut self, walkable: &mut WalkableTiles, (x, y): TileCoord) -> bool {
+        let Some(&current) = walkable.grid().get(x, y) else {
+            return false;
+        };
+        let stroke = self.stroke.get_or_insert(Stroke {
+            walkable: !current,
+            tiles: Vec::new(),
+        });
+        if current == stroke.walkable || walkable.set_walkable_local(x, y, stroke.walkable).is_err()
+        {
+            return false;
+        }
+        stroke.tiles.push((x, y));
+        true
+    }
+
+    /// False when no tile was painted since the last stroke.
+    pub fn end_stroke(&mut self) -> bool {
+        let Some(stroke) = self.stroke.take().filter(|s| !s.tiles.is_empty()) else {
+            return false;
+        };
+        self.done.push(stroke);
+        self.undone.clear();
+        true
+    }
+
+    /// False when there was nothing to undo.
+    pub fn undo(&mut self, walkable: &mut WalkableTiles) -> bool {
+        self.end_stroke();
+        let Some(stroke) = self.done.pop() else {
+            return false;
+        };
+        set_tiles(walkable, &stroke.tiles, !stroke.walkable);
+        self.undone.push(stroke);
+        true
+    }
+
+    /// False when there was nothing to redo.
+    pub fn redo(&mut self, walkable: &mut WalkableTiles) -> bool {
+        self.end_stroke();
+        let Some(stroke) = self.undone.pop() else {
+            return false;
+        };
+        set_tiles(walkable, &stroke.tiles, stroke.walkable);
+        self.done.push(stroke);
+        true
+    }
+}
+
+fn set_tiles(walkable: &mut WalkableTiles, tiles: &[TileCoord], value: bool) {
+    for &(x, y) in tiles {
+        if let Err(e) = walkable.set_walkable_local(x, y, value) {
+            warn!("Could not repaint tile {:?}: {}", (x, y), e);
+        }
+    }
+}
+
+fn toggle_painter(
+    keys: Res<Input<KeyCode>>,
+    mut painter: ResMut<WalkabilityPainter>,
+    overlay: Option<ResMut<WalkabilityOverlay>>,
+    mut level: Query<&mut Level>,
+) {
+    if !keys.just_pressed(TOGGLE_KEY) {
+        return;
+    }
+    painter.enabled = !painter.enabled;
+    end_stroke(&mut painter, &mut level);
+    info!(
+        "Walkability painter {}",
+        if painter.enabled { "on" } else { "off" }
+    );
+    // Painting blind isn't much use
+    if let (true, Some(mut overlay)) = (painter.enabled, overlay) {
+        overlay.enabled = true;
+    }
+}
+
+fn paint_tiles(
+    mouse_buttons: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform)>,
+    tilemap: Query<(&TileLayout, &Transform), (Without<Collider>, Without<LayerParallax>)>,
+    mut level: Query<&mut Level>,
+    mut painter: ResMut<WalkabilityPainter>,
+) {
+    if !mouse_buttons.pressed(MouseButton::Left) {
+        end_stroke(&mut painter, &mut level);
+        return;
+    }
+
+    let Some(cursor) = windows.get_single().ok().and_then(|w| w.cursor_position()) else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        return;
+    };
+    let Some(target) = camera.viewport_to_world_2d(camera_transform, cursor) else {
+        return;
+    };
+    let Ok(mut level) = level.get_single_mut() else {
+        return;
+    };
+    let Some((layout, map_transform)) = tilemap.iter().next() else {
+        return;
+    };
+    let Some(tile) = layout.at(map_transform.translation.xy()).tile_at(target) else {
+        return;
+    };
+
+    // The level is only marked changed once the stroke ends, so that it's validated once per
+    // stroke rather than per tile
+    let level = level.bypass_change_detection();
+    if painter.history.paint(&mut level.walkable_tiles, tile) {
+        level.walkable_tiles_changed();
+        debug!("Painted tile {:?}", tile);
+    }
+}
+
+fn end_stroke(painter: &mut WalkabilityPainter, level: &mut Query<&mut Level>) {
+    if painter.history.end_stroke() {
+        if let Ok(mut level) = level.get_single_mut() {
+            level.set_changed();
+        }
+    }
+}
+
+fn undo_strokes(
+    keys: Res<Input<KeyCode>>,
+    mut level: Query<&mut Level>,
+    mut painter: ResMut<WalkabilityPainter>,
+) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let undo = keys.just_pressed(UNDO_KEY) && !shift;
+    let redo = keys.just_pressed(REDO_KEY) || (keys.just_pressed(UNDO_KEY) && shift);
+    if !undo && !redo {
+        return;
+    }
+    let Ok(mut level) = level.get_single_mut() else {
+        return;
+    };
+
+    let walkable_tiles = &mut level.bypass_change_detection().walkable_tiles;
+    let changed = if undo {
+        painter.history.undo(walkable_tiles)
+    } else {
+        painter.history.redo(walkable_tiles)
+    };
+    if changed {
+        level.walkable_tiles_changed();
+    } else {
+        debug!("Nothing to {}", if undo { "undo" } else { "redo" });
+    }
+}
+
+fn save_walkable_tiles(
+    keys: Res<Input<KeyCode>>,
+    level: Query<&Level>,
+    asset_server: Res<AssetServer>,
+    painter: Res<WalkabilityPainter>,
+) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+        || !keys.just_pressed(SAVE_KEY)
+    {
+        return;
+    }
+    let Ok(level) = level.get_single() else {
+        return;
+    };
+    let Some(asset_path) = asset_server.get_handle_path(&level.cfg) else {
+        warn!("Level config wasn't loaded from a file, not saving it");
+        return;
+    };
+
+    let path = painter.asset_folder.join(asset_path.path());
+    match save_config(&path, &level.all_walkable_tiles()) {
+        Ok(()) => info!("Saved walkable tiles to {}", path.display()),
+        Err(message) => error!("Could not save walkable tiles: {}", message),
+    }
+}
+
+fn save_config(path: &Path, walkable: &WalkableTiles) -> Result<(), String> {
+    let config = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+    let config = write_walkable_tiles(&config, walkable)
+        .map_err(|e| format!("Could not update {}: {e}", path.display()))?;
+    std::fs::write(path, config).map_err(|e| format!("Could not write {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::levels::{grid_from, WalkableTiles};
+
+    use super::PaintHistory;
+
+    fn walkable(rows: &[&str]) -> WalkableTiles {
+        WalkableTiles::from(grid_from(rows))
+    }
+
+    #[test]
+    fn test_strokes_take_the_value_of_their_first_tile() {
+        let mut tiles = walkable(&["#.#", "..."]);
+        let mut history = PaintHistory::default();
+        assert!(history.paint(&mut tiles, (0, 0)));
+        assert!(!history.paint(&mut tiles, (1, 0)));
+        assert!(history.paint(&mut tiles, (2, 0)));
+        assert!(!history.paint(&mut tiles, (3, 0)));
+        assert!(history.end_stroke());
+        assert!(!history.end_stroke());
+        assert!(history.paint(&mut tiles, (1, 1)));
+        assert_eq!(walkable(&["...", ".#."]).grid(), tiles.grid());
+    }
+
+    #[test]
+    fn test_undo_and_redo_strokes() {
+        let original = walkable(&["#.", ".."]);
+        let mut tiles = original.clone();
+        let mut history = PaintHistory::default();
+        history.paint(&mut tiles, (0, 0));
+        history.end_stroke();
+        history.paint(&mut tiles, (0, 1));
+        history.paint(&mut tiles, (1, 1));
+        let painted = tiles.clone();
+
+        assert!(history.undo(&mut tiles));
+        assert_eq!(walkable(&["..", ".."]).grid(), tiles.grid());
+        assert!(history.undo(&mut tiles));
+        assert_eq!(original.grid(), tiles.grid());
+        assert!(!history.undo(&mut tiles));
+
+        assert!(history.redo(&mut tiles));
+        assert!(history.redo(&mut tiles));
+        assert_eq!(painted.grid(), tiles.grid());
+        assert!(!history.redo(&mut tiles));
+
+        // A new stroke drops what was undone
+        history.undo(&mut tiles);
+        history.paint(&mut tiles, (0, 0));
+        assert!(!history.redo(&mut tiles));
+    }
+}
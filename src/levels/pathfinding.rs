@@ -0,0 +1,431 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::Hash;
+
+use bevy::prelude::*;
+
+use super::{elevation::ElevationTiles, BitGrid};
+
+pub type TileCoord = (usize, usize);
+
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Connectivity {
+    #[default]
+    Four,
+    Eight,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Pathfinder {
+    connectivity: Connectivity,
+}
+
+impl Pathfinder {
+    pub fn new(connectivity: Connectivity) -> Self {
+        Self { connectivity }
+    }
+
+    pub fn connectivity(&self) -> Connectivity {
+        self.connectivity
+    }
+
+    /// Ordered list of tiles from `from` to `to`, both included.
+    /// Diagonal steps are only taken when both adjacent orthogonal tiles are walkable.
+    pub fn find_path(
+        &self,
+        grid: &BitGrid,
+        from: TileCoord,
+        to: TileCoord,
+    ) -> Option<Vec<TileCoord>> {
+        self.search(grid, (from, ()), to, |_, _| Some(()))
+    }
+
+    /// Like [`Pathfinder::find_path`] for an entity starting on `floor`, which goes up and down
+    /// floors through connector tiles only.
+    pub fn find_path_across_floors(
+        &self,
+        grid: &BitGrid,
+        elevation: &ElevationTiles,
+        from: TileCoord,
+        floor: u8,
+        to: TileCoord,
+    ) -> Option<Vec<TileCoord>> {
+        self.search(grid, (from, floor), to, |((x, y), floor), (nx, ny)| {
+            elevation
+                .is_passable(nx, ny, &elevation.reachable_floors(floor, x, y))
+                .then(|| elevation.floor_after_step(floor, nx, ny))
+        })
+    }
+
+    /// A* over tiles paired with a layer, such as the floor. `step` gives the layer after stepping
+    /// onto a walkable neighbour, or `None` when it can't be stepped on.
+    fn search<L: Copy + Ord + Hash>(
+        &self,
+        grid: &BitGrid,
+        start: (TileCoord, L),
+        to: TileCoord,
+        step: impl Fn((TileCoord, L), TileCoord) -> Option<L>,
+    ) -> Option<Vec<TileCoord>> {
+        let from = start.0;
+        if !is_walkable(grid, from) || !is_walkable(grid, to) {
+            return None;
+        }
+        if from == to {
+            return Some(vec![from]);
+        }
+
+        let mut open = BinaryHeap::new();
+        let mut came_from = HashMap::new();
+        let mut cost_so_far = HashMap::new();
+
+        cost_so_far.insert(start, 0);
+        open.push(Reverse((self.heuristic(from, to), 0, start)));
+
+        while let Some(Reverse((_, cost, current))) = open.pop() {
+            if current.0 == to {
+                let path = reconstruct(&came_from, start, current);
+                return Some(path.into_iter().map(|(tile, _)| tile).collect());
+            }
+            if cost > cost_so_far.get(&current).copied().unwrap_or(u32::MAX) {
+                continue;
+            }
+
+            for (next, step_cost) in self.neighbours(grid, current, &step) {
+                let next_cost = cost + step_cost;
+                if next_cost < cost_so_far.get(&next).copied().unwrap_or(u32::MAX) {
+                    cost_so_far.insert(next, next_cost);
+                    came_from.insert(next, current);
+                    open.push(Reverse((
+                        next_cost + self.heuristic(next.0, to),
+                        next_cost,
+                        next,
+                    )));
+                }
+            }
+        }
+
+        None
+    }
+
+    fn heuristic(&self, (ax, ay): TileCoord, (bx, by): TileCoord) -> u32 {
+        let dx = ax.abs_diff(bx) as u32;
+        let dy = ay.abs_diff(by) as u32;
+        match self.connectivity {
+            Connectivity::Four => STRAIGHT_COST * (dx + dy),
+            Connectivity::Eight => {
+                STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+            }
+        }
+    }
+
+    fn neighbours<L: Copy>(
+        &self,
+        grid: &BitGrid,
+        (tile, layer): (TileCoord, L),
+        step: &impl Fn((TileCoord, L), TileCoord) -> Option<L>,
+    ) -> Vec<((TileCoord, L), u32)> {
+        let mut result = Vec::with_capacity(8);
+        let (x, y) = (tile.0 as i64, tile.1 as i64);
+        let walkable = |dx: i64, dy: i64| -> Option<(TileCoord, L)> {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 {
+                return None;
+            }
+            let pos = (nx as usize, ny as usize);
+            if !is_walkable(grid, pos) {
+                return None;
+            }
+            step((tile, layer), pos).map(|layer| (pos, layer))
+        };
+
+        for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            if let Some(next) = walkable(dx, dy) {
+                result.push((next, STRAIGHT_COST));
+            }
+        }
+
+        if self.connectivity == Connectivity::Eight {
+            for (dx, dy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                // No corner cutting
+                if walkable(dx, 0).is_none() || walkable(0, dy).is_none() {
+                    continue;
+                }
+                if let Some(next) = walkable(dx, dy) {
+                    result.push((next, DIAGONAL_COST));
+                }
+            }
+        }
+
+        result
+    }
+}
+
+fn is_walkable(grid: &BitGrid, (x, y): TileCoord) -> bool {
+    grid.get(x, y).copied().unwrap_or(false)
+}
+
+fn reconstruct<T: Copy + Eq + Hash>(came_from: &HashMap<T, T>, from: T, to: T) -> Vec<T> {
+    let mut path = vec![to];
+    let mut current = to;
+    while current != from {
+        current = came_from[&current];
+        path.push(current);
+    }
+    path.reverse();
+    path
+}
+
+/// Paths kept by [`CachedPathfinder`], the least recently used ones are dropped first.
+const MAX_CACHED_PATHS: usize = 256;
+
+/// Grid a path is searched on: the [`Level::generation`](super::Level::generation) of its
+/// walkable tiles, and the floor paths start from on levels with elevation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GridKey {
+    pub generation: u64,
+    pub floor: Option<u8>,
+}
+
+/// [`Pathfinder`] that memoizes routes until the level's walkable tiles change.
+#[derive(Resource, Default)]
+pub struct CachedPathfinder {
+    pathfinder: Pathfinder,
+    generation: Option<u64>,
+    paths: HashMap<(Option<u8>, TileCoord, TileCoord), CachedPath>,
+    uses: u64,
+}
+
+struct CachedPath {
+    path: Option<Vec<TileCoord>>,
+    last_used: u64,
+}
+
+impl CachedPathfinder {
+    pub fn new(connectivity: Connectivity) -> Self {
+        Self {
+            pathfinder: Pathfinder::new(connectivity),
+            ..default()
+        }
+    }
+
+    /// Path on `grid`, which `key` stands for, across floors when there's `elevation`.
+    pub fn find_path(
+        &mut self,
+        key: GridKey,
+        grid: &BitGrid,
+        elevation: Option<&ElevationTiles>,
+        from: TileCoord,
+        to: TileCoord,
+    ) -> Option<Vec<TileCoord>> {
+        if self.generation != Some(key.generation) {
+            debug!("Grid changed, dropping {} cached paths", self.paths.len());
+            self.paths.clear();
+            self.generation = Some(key.generation);
+        }
+
+        self.uses += 1;
+        let entry = (key.floor, from, to);
+        if let Some(cached) = self.paths.get_mut(&entry) {
+            cached.last_used = self.uses;
+            return cached.path.clone();
+        }
+
+        if self.paths.len() >= MAX_CACHED_PATHS {
+            let oldest = self
+                .paths
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(&entry, _)| entry);
+            if let Some(oldest) = oldest {
+                self.paths.remove(&oldest);
+            }
+        }
+        let path = match (elevation, key.floor) {
+            (Some(elevation), Some(floor)) => self
+                .pathfinder
+                .find_path_across_floors(grid, elevation, from, floor, to),
+            _ => self.pathfinder.find_path(grid, from, to),
+        };
+        self.paths.insert(
+            entry,
+            CachedPath {
+                path: path.clone(),
+                last_used: self.uses,
+            },
+        );
+        path
+    }
+
+    pub fn cached_paths(&self) -> usize {
+        self.paths.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::levels::{
+        elevation::{ElevationTiles, TileElevation},
+        grid_from, BitGrid, Grid,
+    };
+
+    use super::{CachedPathfinder, Connectivity, GridKey, Pathfinder, MAX_CACHED_PATHS};
+
+    const GROUND: TileElevation = TileElevation {
+        floor: 0,
+        connector: false,
+    };
+    const STAIRS: TileElevation = TileElevation {
+        floor: 0,
+        connector: true,
+    };
+    const PLATEAU: TileElevation = TileElevation {
+        floor: 1,
+        connector: false,
+    };
+
+    #[test]
+    fn test_path_to_self() {
+        let grid = BitGrid::filled(3, 3, true);
+        let pathfinder = Pathfinder::new(Connectivity::Four);
+        assert_eq!(
+            Some(vec![(1, 1)]),
+            pathfinder.find_path(&grid, (1, 1), (1, 1))
+        )
+    }
+
+    #[test]
+    fn test_four_connected_goes_around_wall() {
+        let grid = grid_from(&[
+            ".....", //
+            ".###.", //
+            ".....",
+        ]);
+        let path = Pathfinder::new(Connectivity::Four)
+            .find_path(&grid, (0, 1), (4, 1))
+            .unwrap();
+        assert_eq!(7, path.len());
+        assert_eq!(Some(&(0, 1)), path.first());
+        assert_eq!(Some(&(4, 1)), path.last());
+        for w in path.windows(2) {
+            let (a, b) = (w[0], w[1]);
+            assert_eq!(1, a.0.abs_diff(b.0) + a.1.abs_diff(b.1));
+        }
+    }
+
+    #[test]
+    fn test_eight_connected_takes_diagonals() {
+        let grid = BitGrid::filled(5, 5, true);
+        let path = Pathfinder::new(Connectivity::Eight)
+            .find_path(&grid, (0, 0), (4, 4))
+            .unwrap();
+        assert_eq!(vec![(0, 0), (1, 1), (2, 2), (3, 3), (4, 4)], path);
+    }
+
+    #[test]
+    fn test_eight_connected_does_not_cut_corners() {
+        let grid = grid_from(&[
+            ".#", //
+            "..",
+        ]);
+        let path = Pathfinder::new(Connectivity::Eight)
+            .find_path(&grid, (0, 0), (1, 1))
+            .unwrap();
+        assert_eq!(vec![(0, 0), (0, 1), (1, 1)], path);
+    }
+
+    #[test]
+    fn test_no_path_when_walled_off() {
+        let grid = grid_from(&[
+            "..#..", //
+            "..#..", //
+            "..#..",
+        ]);
+        let pathfinder = Pathfinder::new(Connectivity::Eight);
+        assert_eq!(None, pathfinder.find_path(&grid, (0, 0), (4, 2)));
+        assert_eq!(None, pathfinder.find_path(&grid, (0, 0), (2, 0)));
+        assert_eq!(None, pathfinder.find_path(&grid, (0, 0), (10, 10)));
+    }
+
+    #[test]
+    fn test_paths_take_stairs_between_floors() {
+        // Ground, stairs up to the plateau on the right, cliff in between further down
+        let elevation = ElevationTiles::from(
+            Grid::try_from(vec![
+                vec![GROUND, GROUND, STAIRS, PLATEAU],
+                vec![GROUND, GROUND, GROUND, PLATEAU],
+            ])
+            .unwrap(),
+        );
+        let grid = BitGrid::filled(2, 4, true);
+        let pathfinder = Pathfinder::new(Connectivity::Four);
+
+        assert_eq!(
+            Some(vec![(1, 0), (2, 0), (3, 0), (3, 1)]),
+            pathfinder.find_path_across_floors(&grid, &elevation, (1, 0), 0, (3, 1))
+        );
+        assert_eq!(
+            Some(vec![(3, 1), (3, 0), (2, 0), (2, 1)]),
+            pathfinder.find_path_across_floors(&grid, &elevation, (3, 1), 1, (2, 1))
+        );
+        // Without the stairs the plateau is out of reach
+        let mut grid = grid;
+        grid.set(2, 0, false).unwrap();
+        assert_eq!(
+            None,
+            pathfinder.find_path_across_floors(&grid, &elevation, (1, 1), 0, (3, 1))
+        );
+    }
+
+    #[test]
+    fn test_cached_pathfinder_invalidates_on_grid_change() {
+        let mut grid = BitGrid::filled(4, 4, true);
+        let mut cached = CachedPathfinder::new(Connectivity::Four);
+        let key = GridKey {
+            generation: 0,
+            floor: None,
+        };
+
+        assert!(cached.find_path(key, &grid, None, (0, 0), (3, 0)).is_some());
+        assert_eq!(1, cached.cached_paths());
+
+        for y in 0..4 {
+            grid.set(1, y, false).unwrap();
+        }
+        // Same generation, same path
+        assert!(cached.find_path(key, &grid, None, (0, 0), (3, 0)).is_some());
+        let key = GridKey {
+            generation: 1,
+            ..key
+        };
+        assert_eq!(None, cached.find_path(key, &grid, None, (0, 0), (3, 0)));
+        assert_eq!(1, cached.cached_paths());
+    }
+
+    #[test]
+    fn test_cached_pathfinder_drops_least_recently_used_paths() {
+        let grid = BitGrid::filled(MAX_CACHED_PATHS + 1, 2, true);
+        let blocked = BitGrid::filled(1, 1, false);
+        let mut cached = CachedPathfinder::new(Connectivity::Four);
+        let key = GridKey {
+            generation: 0,
+            floor: None,
+        };
+
+        for y in 0..MAX_CACHED_PATHS {
+            cached.find_path(key, &grid, None, (0, 0), (0, y));
+        }
+        cached.find_path(key, &grid, None, (0, 0), (0, 0));
+        cached.find_path(key, &grid, None, (0, 0), (0, MAX_CACHED_PATHS));
+        assert_eq!(MAX_CACHED_PATHS, cached.cached_paths());
+        // The first path was used again, the second one is the oldest and gets searched again
+        assert!(cached
+            .find_path(key, &blocked, None, (0, 0), (0, 0))
+            .is_some());
+        assert!(cached
+            .find_path(key, &blocked, None, (0, 0), (0, 1))
+            .is_none());
+    }
+}
@@ -0,0 +1,36 @@
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy_asset_loader::prelude::*;
+use serde::Deserialize;
+
+/// Ordered list of levels, loaded from `levels.manifest.json`.
+#[derive(Deserialize, TypeUuid, TypePath, Debug)]
+#[uuid = "9d4c1f0e-5b8a-4f1e-9a57-3c0f2d6e8b41"]
+pub struct LevelManifest {
+    pub levels: Vec<LevelEntry>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LevelEntry {
+    pub name: String,
+    pub map: String,
+    pub config: String,
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct LevelManifestAsset {
+    #[asset(path = "levels/levels.manifest.json")]
+    pub manifest: Handle<LevelManifest>,
+}
+
+impl LevelManifestAsset {
+    pub fn entry<'a>(
+        &self,
+        manifests: &'a Assets<LevelManifest>,
+        index: usize,
+    ) -> Option<&'a LevelEntry> {
+        manifests
+            .get(&self.manifest)
+            .and_then(|manifest| manifest.levels.get(index))
+    }
+}
@@ -0,0 +1,248 @@
+use bevy::{asset::LoadState, prelude::*};
+
+use crate::{
+    helpers::tiled::{RemoveMap, TiledMap, TiledMapBundle},
+    player::Player,
+};
+
+use super::{
+    objects::{MapObject, ObjectProperties, TriggerEvent},
+    registry::{LevelEntry, LevelManifest, LevelManifestAsset},
+    Level, LevelBundle, LevelConfig,
+};
+
+/// Trigger object name advancing to the next level when the player enters it.
+pub const EXIT_OBJECT: &str = "exit";
+/// Optional int property of the exit picking the level index instead of the next one.
+const NEXT_LEVEL_PROPERTY: &str = "next_level";
+
+const FADE_SECONDS: f32 = 0.4;
+
+/// Request to switch to the level at this index of the manifest.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct LoadLevel(pub usize);
+
+#[derive(Resource, Default, Debug)]
+pub struct CurrentLevel(pub Option<usize>);
+
+#[derive(Resource)]
+struct LevelTransition {
+    target: usize,
+    phase: TransitionPhase,
+    timer: Timer,
+}
+
+#[derive(Clone)]
+enum TransitionPhase {
+    FadingOut,
+    Loading {
+        map: Handle<TiledMap>,
+        config: Handle<LevelConfig>,
+    },
+    FadingIn,
+}
+
+#[derive(Component)]
+struct FadeOverlay;
+
+pub struct LevelTransitionPlugin;
+
+impl Plugin for LevelTransitionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CurrentLevel>()
+            .add_event::<LoadLevel>()
+            .add_systems(
+                Update,
+                (advance_on_exit, start_transition, run_transition).chain(),
+            );
+    }
+}
+
+fn start_transition(
+    mut commands: Commands,
+    mut requests: EventReader<LoadLevel>,
+    transition: Option<Res<LevelTransition>>,
+    manifest_res: Option<Res<LevelManifestAsset>>,
+    manifests: Res<Assets<LevelManifest>>,
+) {
+    let Some(LoadLevel(index)) = requests.iter().last().copied() else {
+        return;
+    };
+    if transition.is_some() {
+        warn!("Level transition is already running, ignoring request for level {index}");
+        return;
+    }
+    let Some(entry) = manifest_res.and_then(|m| m.entry(&manifests, index).cloned()) else {
+        warn!("No level {index} in the manifest");
+        return;
+    };
+
+    info!("Switching to level {index} \"{}\"", entry.name);
+    commands.insert_resource(LevelTransition {
+        target: index,
+        phase: TransitionPhase::FadingOut,
+        timer: Timer::from_seconds(FADE_SECONDS, TimerMode::Once),
+    });
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.).into(),
+            z_index: ZIndex::Global(i32::MAX),
+            ..default()
+        },
+        FadeOverlay,
+    ));
+}
+
+fn run_transition(
+    mut commands: Commands,
+    time: Res<Time>,
+    transition: Option<ResMut<LevelTransition>>,
+    mut overlay: Query<(Entity, &mut BackgroundColor), With<FadeOverlay>>,
+    asset_server: Res<AssetServer>,
+    manifest_res: Option<Res<LevelManifestAsset>>,
+    manifests: Res<Assets<LevelManifest>>,
+    maps: Res<Assets<TiledMap>>,
+    level_configs: Res<Assets<LevelConfig>>,
+    levels: Query<Entity, With<Level>>,
+    players: Query<Entity, With<Player>>,
+    mut current: ResMut<CurrentLevel>,
+) {
+    let Some(mut transition) = transition else {
+        return;
+    };
+    transition.timer.tick(time.delta());
+    let progress = transition.timer.percent();
+
+    let mut set_alpha = |alpha: f32| {
+        for (_, mut color) in overlay.iter_mut() {
+            color.0.set_a(alpha);
+        }
+    };
+
+    match transition.phase.clone() {
+        TransitionPhase::FadingOut => {
+            set_alpha(progress);
+            if !transition.timer.finished() {
+                return;
+            }
+
+            // Checked before tearing anything down, so that the current level stays otherwise
+            let Some(entry) = manifest_res
+                .as_ref()
+                .and_then(|m| m.entry(&manifests, transition.target))
+            else {
+                error!("Level {} disappeared from the manifest", transition.target);
+                end_transition(&mut commands, overlay.iter().map(|(e, _)| e));
+                return;
+            };
+
+            // The player is respawned on the spawn point of the next level
+            for level_entity in levels.iter() {
+                commands.entity(level_entity).insert(RemoveMap);
+            }
+            for player in players.iter() {
+                commands.entity(player).despawn_recursive();
+            }
+            transition.phase = load_entry(&asset_server, entry);
+        }
+        TransitionPhase::Loading { map, config } => {
+            set_alpha(1.);
+            if [map.id(), config.id()]
+                .into_iter()
+                .any(|id| asset_server.get_load_state(id) == LoadState::Failed)
+            {
+                // Back to the level the player left, if that isn't the one failing
+                let previous = current.0.filter(|&index| index != transition.target);
+                let previous_entry = previous
+                    .zip(manifest_res.as_ref())
+                    .and_then(|(index, m)| Some((index, m.entry(&manifests, index)?)));
+                match previous_entry {
+                    Some((index, entry)) => {
+                        error!(
+                            "Failed to load level {}, reloading level {index}",
+                            transition.target
+                        );
+                        transition.target = index;
+                        transition.phase = load_entry(&asset_server, entry);
+                    }
+                    None => {
+                        error!("Failed to load level {}", transition.target);
+                        current.0 = None;
+                        end_transition(&mut commands, overlay.iter().map(|(e, _)| e));
+                    }
+                }
+                return;
+            }
+            let (Some(tiled_map), Some(cfg)) = (maps.get(&map), level_configs.get(&config)) else {
+                return;
+            };
+
+            commands.spawn(LevelBundle {
+                tilemap: TiledMapBundle {
+                    tiled_map: map.clone(),
+                    ..default()
+                },
+                level: Level::new(config.clone(), cfg, tiled_map),
+            });
+            current.0 = Some(transition.target);
+            transition.phase = TransitionPhase::FadingIn;
+            transition.timer.reset();
+        }
+        TransitionPhase::FadingIn => {
+            set_alpha(1. - progress);
+            if transition.timer.finished() {
+                end_transition(&mut commands, overlay.iter().map(|(e, _)| e));
+            }
+        }
+    }
+}
+
+fn load_entry(asset_server: &AssetServer, entry: &LevelEntry) -> TransitionPhase {
+    TransitionPhase::Loading {
+        map: asset_server.load(entry.map.as_str()),
+        config: asset_server.load(entry.config.as_str()),
+    }
+}
+
+/// Removes the fade overlay along with the transition, whichever way it ended.
+fn end_transition(commands: &mut Commands, overlays: impl Iterator<Item = Entity>) {
+    for overlay in overlays {
+        commands.entity(overlay).despawn_recursive();
+    }
+    commands.remove_resource::<LevelTransition>();
+}
+
+fn advance_on_exit(
+    mut triggers: EventReader<TriggerEvent>,
+    exits: Query<(&MapObject, &ObjectProperties)>,
+    players: Query<(), With<Player>>,
+    current: Res<CurrentLevel>,
+    mut requests: EventWriter<LoadLevel>,
+) {
+    for event in triggers.iter() {
+        let TriggerEvent::Entered { trigger, entity } = *event else {
+            continue;
+        };
+        if !players.contains(entity) {
+            continue;
+        }
+        let Ok((object, properties)) = exits.get(trigger) else {
+            continue;
+        };
+        if object.name != EXIT_OBJECT {
+            continue;
+        }
+
+        let next = properties
+            .int(NEXT_LEVEL_PROPERTY)
+            .map(|index| index.max(0) as usize)
+            .unwrap_or_else(|| current.0.map(|index| index + 1).unwrap_or(0));
+        requests.send(LoadLevel(next));
+    }
+}
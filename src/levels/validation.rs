@@ -0,0 +1,310 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use anyhow::Result;
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::BoxedFuture,
+};
+use jsonschema::JSONSchema;
+
+use crate::helpers::tiled::TiledMap;
+
+use super::{
+    encoding::{WalkableTilesDto, WALKABLE_TILES_KEY},
+    Level, LevelConfig, LevelError, WalkableTiles,
+};
+
+/// Level file rejected when loading, with everything wrong in it.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct InvalidLevelFile {
+    pub path: PathBuf,
+    pub errors: Vec<LevelError>,
+}
+
+/// Files rejected by [`LevelConfigLoader`], which can't send events, until
+/// [`send_invalid_level_files`] does.
+#[derive(Resource, Clone, Default)]
+pub struct RejectedLevelFiles(Arc<Mutex<Vec<InvalidLevelFile>>>);
+
+/// Loads level configs with [`parse_config`], so that broken files are reported rather than
+/// only logged by the asset server.
+pub struct LevelConfigLoader {
+    rejected: RejectedLevelFiles,
+}
+
+impl LevelConfigLoader {
+    pub fn new(rejected: RejectedLevelFiles) -> Self {
+        Self { rejected }
+    }
+}
+
+impl AssetLoader for LevelConfigLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let path = load_context.path().to_path_buf();
+            match parse_config(bytes) {
+                Ok(cfg) => {
+                    load_context.set_default_asset(LoadedAsset::new(cfg));
+                    Ok(())
+                }
+                Err(errors) => {
+                    if let Ok(mut rejected) = self.rejected.0.lock() {
+                        rejected.push(InvalidLevelFile {
+                            path: path.clone(),
+                            errors,
+                        });
+                    }
+                    Err(anyhow::anyhow!("Invalid level config {}", path.display()))
+                }
+            }
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ccwl.json"]
+    }
+}
+
+pub fn send_invalid_level_files(
+    rejected: Res<RejectedLevelFiles>,
+    mut events: EventWriter<InvalidLevelFile>,
+) {
+    if let Ok(mut rejected) = rejected.0.lock() {
+        events.send_batch(rejected.drain(..));
+    }
+}
+
+/// Level config file contents, checked against the schema before being deserialized. Walkable
+/// tiles are decoded on their own, to report where their rows are wrong.
+pub fn parse_config(bytes: &[u8]) -> Result<LevelConfig, Vec<LevelError>> {
+    let mut json: serde_json::Value = serde_json::from_slice(bytes).map_err(|e| {
+        vec![LevelError::InvalidConfig {
+            message: e.to_string(),
+        }]
+    })?;
+    let schema_errors = validate_schema(&json);
+    if !schema_errors.is_empty() {
+        return Err(schema_errors);
+    }
+
+    let walkable_tiles = match json
+        .as_object_mut()
+        .and_then(|c| c.remove(WALKABLE_TILES_KEY))
+    {
+        Some(walkable_tiles) => Some(decode_walkable_tiles(walkable_tiles)?),
+        None => None,
+    };
+    let cfg = serde_json::from_value(json).map_err(|e| {
+        vec![LevelError::InvalidConfig {
+            message: e.to_string(),
+        }]
+    })?;
+    Ok(LevelConfig {
+        walkable_tiles,
+        ..cfg
+    })
+}
+
+fn decode_walkable_tiles(json: serde_json::Value) -> Result<WalkableTiles, Vec<LevelError>> {
+    let dto: WalkableTilesDto = serde_json::from_value(json).map_err(|e| {
+        vec![LevelError::SchemaViolation {
+            pointer: format!("/{WALKABLE_TILES_KEY}"),
+            message: e.to_string(),
+        }]
+    })?;
+    WalkableTiles::try_from(dto).map_err(|error| vec![LevelError::InvalidWalkableTiles { error }])
+}
+
+/// Schema level configs are checked against, see `assets/levels/ccwl.schema.json`.
+pub const LEVEL_SCHEMA: &str = include_str!("../../assets/levels/ccwl.schema.json");
+
+/// Everything wrong with the level made of the map and config files, read from disk rather than
+/// through the asset server. Empty when the level is fine.
+pub fn validate_level_files(map_path: &Path, config_path: &Path) -> Vec<LevelError> {
+    let mut errors = Vec::new();
+
+    let cfg = match std::fs::read(config_path) {
+        Ok(bytes) => parse_config(&bytes).map_err(|e| errors.extend(e)).ok(),
+        Err(e) => {
+            errors.push(LevelError::InvalidConfig {
+                message: format!("Could not read {}: {e}", config_path.display()),
+            });
+            None
+        }
+    };
+
+    let map = match tiled::Loader::new().load_tmx_map(map_path) {
+        Ok(map) => {
+            errors.extend(validate_images(&map));
+            Some(TiledMap::new(map, Vec::new()))
+        }
+        Err(e) => {
+            errors.push(LevelError::InvalidMap {
+                message: format!("Could not load {}: {e}", map_path.display()),
+            });
+            None
+        }
+    };
+
+    if let (Some(cfg), Some(map)) = (cfg, map) {
+        let level = Level::new(Handle::default(), &cfg, &map);
+        errors.extend(level.validate(&cfg, &map));
+    }
+    errors
+}
+
+pub fn validate_schema(config: &serde_json::Value) -> Vec<LevelError> {
+    // Compiled once, every level file is checked against it
+    static SCHEMA: OnceLock<JSONSchema> = OnceLock::new();
+    let schema = SCHEMA.get_or_init(|| {
+        let schema = serde_json::from_str(LEVEL_SCHEMA).expect("Level schema isn't JSON");
+        JSONSchema::compile(&schema).expect("Level schema isn't a JSON schema")
+    });
+    let errors = match schema.validate(config) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors
+            .map(|e| LevelError::SchemaViolation {
+                pointer: e.instance_path.to_string(),
+                message: e.to_string(),
+            })
+            .collect(),
+    };
+    errors
+}
+
+/// Tileset images, single or per tile, missing on disk.
+pub fn validate_images(map: &tiled::Map) -> Vec<LevelError> {
+    let mut errors = Vec::new();
+    for tileset in map.tilesets() {
+        let tile_images = tileset.tiles().filter_map(|(_, tile)| tile.image.clone());
+        for image in tileset.image.clone().into_iter().chain(tile_images) {
+            if !image.source.exists() {
+                errors.push(LevelError::MissingImage {
+                    tileset: tileset.name.clone(),
+                    path: image.source,
+                });
+            }
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use bevy::math::{UVec2, Vec2};
+
+    use crate::levels::{encoding::EncodingError, GridError, LevelError};
+
+    use super::{parse_config, validate_level_files, validate_schema};
+
+    #[test]
+    fn test_game_levels_are_valid() {
+        assert_eq!(
+            Vec::<LevelError>::new(),
+            validate_level_files(
+                Path::new("assets/levels/level1.tmx"),
+                Path::new("assets/levels/level1.ccwl.json")
+            )
+        );
+    }
+
+    #[test]
+    fn test_broken_level_reports_every_problem() {
+        let errors = validate_level_files(
+            Path::new("tests/level/walkable.tmx"),
+            Path::new("tests/level/islands.ccwl.json"),
+        );
+        assert_eq!(
+            vec![
+                LevelError::MissingImage {
+                    tileset: "Terrain".to_string(),
+                    path: PathBuf::from("tests/level/terrain.png"),
+                },
+                LevelError::TileSizeMismatch {
+                    config: 32.,
+                    map: Vec2::splat(64.),
+                },
+                LevelError::WalkableSizeMismatch {
+                    walkable: UVec2::new(4, 2),
+                    map: UVec2::new(3, 2),
+                },
+                LevelError::SpawnNotWalkable {
+                    spawn_point: Vec2::new(0., 1.),
+                },
+                LevelError::UnreachableTiles {
+                    first: UVec2::new(3, 0),
+                    tiles: 2,
+                },
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn test_schema_violations_point_at_the_value() {
+        let config = serde_json::json!({
+            "tile_size": 64,
+            "walkable_tiles": { "rows": [".x"] },
+            "spawn_point": [1],
+        });
+        let pointers: Vec<String> = validate_schema(&config)
+            .into_iter()
+            .filter_map(|e| match e {
+                LevelError::SchemaViolation { pointer, .. } => Some(pointer),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec!["/walkable_tiles", "/spawn_point"], pointers);
+    }
+
+    #[test]
+    fn test_parse_config_reads_level_files() {
+        let cfg = parse_config(&std::fs::read("assets/levels/level1.ccwl.json").unwrap()).unwrap();
+        // Walkability comes from the map's collision layer
+        assert!(cfg.walkable_tiles.is_none());
+        assert_eq!(64., cfg.tile_size);
+        assert_eq!(Vec2::new(9., 9.), cfg.spawn_point);
+    }
+
+    #[test]
+    fn test_parse_config_reports_mismatched_rows() {
+        let config = r##"{"tile_size": 32, "walkable_tiles": {"rows": ["...", "..", "..."]}}"##;
+        assert_eq!(
+            Err(vec![LevelError::InvalidWalkableTiles {
+                error: EncodingError::Grid(GridError::MismatchedWidth {
+                    row: 1,
+                    expected: 3,
+                    found: 2
+                })
+            }]),
+            parse_config(config.as_bytes()).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_parse_config_rejects_broken_files() {
+        assert!(matches!(
+            parse_config(b"{\"tile_size\": 32,").err().as_deref(),
+            Some([LevelError::InvalidConfig { .. }])
+        ));
+        assert!(matches!(
+            parse_config(br#"{"tile_size": "32"}"#).err().as_deref(),
+            Some([LevelError::SchemaViolation { pointer, .. }]) if pointer == "/tile_size"
+        ));
+        // Triggers, enemies and buildings are objects of the map
+        assert!(matches!(
+            parse_config(br#"{"tile_size": 32, "triggers": []}"#).err().as_deref(),
+            Some([LevelError::SchemaViolation { pointer, .. }]) if pointer.is_empty()
+        ));
+    }
+}
@@ -0,0 +1,191 @@
+use std::{borrow::Cow, collections::VecDeque};
+
+use bevy::{math::Vec3Swizzles, prelude::*};
+
+use crate::{
+    collision::Collider,
+    helpers::{
+        coordinate_utils::{MapOrientation, TileLayout},
+        tiled::LayerParallax,
+    },
+    player::{Player, TilePath},
+};
+
+use super::{
+    coordinator::SnappedToWalkable,
+    elevation::{Floor, FloorRange},
+    Level,
+};
+
+/// Snaps kept on screen, older ones are dropped first.
+const MAX_SNAPS: usize = 8;
+const TOGGLE_KEY: KeyCode = KeyCode::F3;
+
+const WALKABLE_COLOR: Color = Color::rgba(0.2, 0.9, 0.3, 0.6);
+const BLOCKED_COLOR: Color = Color::rgba(0.9, 0.2, 0.2, 0.6);
+const PLAYER_TILE_COLOR: Color = Color::YELLOW;
+const SNAP_COLOR: Color = Color::ORANGE;
+const PATH_COLOR: Color = Color::CYAN;
+
+/// Draws walkability over the live map, toggled with F3: walkable and blocked tiles, the tile
+/// under the player, where entities were snapped back to and the paths being walked.
+pub struct WalkabilityOverlayPlugin;
+
+impl Plugin for WalkabilityOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WalkabilityOverlay>().add_systems(
+            Update,
+            (
+                toggle_walkability_overlay,
+                record_snaps,
+                (draw_walkable_tiles, draw_snaps, draw_paths)
+                    .run_if(|overlay: Res<WalkabilityOverlay>| overlay.enabled),
+            )
+                .chain(),
+        );
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct WalkabilityOverlay {
+    pub enabled: bool,
+    snaps: VecDeque<SnappedToWalkable>,
+}
+
+impl WalkabilityOverlay {
+    pub fn push_snap(&mut self, snap: SnappedToWalkable) {
+        if self.snaps.len() == MAX_SNAPS {
+            self.snaps.pop_front();
+        }
+        self.snaps.push_back(snap);
+    }
+
+    pub fn snaps(&self) -> impl Iterator<Item = &SnappedToWalkable> {
+        self.snaps.iter()
+    }
+}
+
+fn toggle_walkability_overlay(keys: Res<Input<KeyCode>>, mut overlay: ResMut<WalkabilityOverlay>) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        overlay.enabled = !overlay.enabled;
+    }
+}
+
+/// Kept while the overlay is hidden too, so that it shows the latest snaps once toggled on.
+fn record_snaps(
+    mut snaps: EventReader<SnappedToWalkable>,
+    mut overlay: ResMut<WalkabilityOverlay>,
+) {
+    for snap in snaps.iter() {
+        overlay.push_snap(*snap);
+    }
+}
+
+fn draw_walkable_tiles(
+    mut gizmos: Gizmos,
+    level: Query<&Level>,
+    tilemap: Query<(&TileLayout, &Transform), (Without<Collider>, Without<LayerParallax>)>,
+    player: Query<(&Transform, &Collider, Option<&Floor>), With<Player>>,
+) {
+    let Ok(level) = level.get_single() else {
+        return;
+    };
+    // Every layer shares the map grid, so the first one is enough
+    let Some((layout, map_transform)) = tilemap.iter().next() else {
+        return;
+    };
+    let layout = layout.at(map_transform.translation.xy());
+    let player = player.get_single().ok();
+
+    // Tiles on other floors than the player's are blocked for them
+    let walkable_tiles = match player.and_then(|(_, _, floor)| floor) {
+        Some(floor) if level.elevation.is_some() => {
+            Cow::Owned(level.walkable_tiles_on(&FloorRange::single(**floor)))
+        }
+        _ => Cow::Borrowed(&level.walkable_tiles),
+    };
+    walkable_tiles.grid().for_each(|x, y, &walkable| {
+        let color = if walkable {
+            WALKABLE_COLOR
+        } else {
+            BLOCKED_COLOR
+        };
+        draw_tile(&mut gizmos, &layout, x, y, 0.8, color);
+    });
+
+    let player_tile = player.and_then(|(transform, collider, _)| {
+        layout.tile_at(transform.translation.xy() + collider.offset)
+    });
+    if let Some((x, y)) = player_tile {
+        draw_tile(&mut gizmos, &layout, x, y, 1., PLAYER_TILE_COLOR);
+    }
+}
+
+fn draw_snaps(mut gizmos: Gizmos, overlay: Res<WalkabilityOverlay>) {
+    for snap in overlay.snaps() {
+        gizmos.line_2d(snap.from, snap.to, SNAP_COLOR);
+        gizmos.circle_2d(snap.to, 4., SNAP_COLOR);
+    }
+}
+
+fn draw_paths(mut gizmos: Gizmos, walkers: Query<(&Transform, &Collider, &TilePath)>) {
+    for (transform, collider, path) in walkers.iter() {
+        let start = transform.translation.xy() + collider.offset;
+        gizmos.linestrip_2d(
+            std::iter::once(start).chain(path.waypoints().copied()),
+            PATH_COLOR,
+        );
+        for waypoint in path.waypoints() {
+            gizmos.circle_2d(*waypoint, 3., PATH_COLOR);
+        }
+    }
+}
+
+/// Outline of tile (x, y) scaled by `scale` around its center, diamonds on isometric maps.
+fn draw_tile(
+    gizmos: &mut Gizmos,
+    layout: &TileLayout,
+    x: usize,
+    y: usize,
+    scale: f32,
+    color: Color,
+) {
+    let center = layout.tile_to_world(x, y);
+    let half = layout.tile_size * scale / 2.;
+    match layout.orientation {
+        MapOrientation::Isometric => gizmos.linestrip_2d(
+            [
+                center + Vec2::new(0., half.y),
+                center + Vec2::new(half.x, 0.),
+                center - Vec2::new(0., half.y),
+                center - Vec2::new(half.x, 0.),
+                center + Vec2::new(0., half.y),
+            ],
+            color,
+        ),
+        _ => gizmos.rect_2d(center, 0., half * 2., color),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::levels::coordinator::SnappedToWalkable;
+
+    use super::{WalkabilityOverlay, MAX_SNAPS};
+
+    #[test]
+    fn test_overlay_keeps_latest_snaps() {
+        let mut overlay = WalkabilityOverlay::default();
+        for i in 0..MAX_SNAPS + 2 {
+            overlay.push_snap(SnappedToWalkable {
+                entity: Entity::PLACEHOLDER,
+                from: Vec2::ZERO,
+                to: Vec2::splat(i as f32),
+            });
+        }
+        assert_eq!(MAX_SNAPS, overlay.snaps().count());
+        assert_eq!(Some(Vec2::splat(2.)), overlay.snaps().next().map(|s| s.to));
+    }
+}
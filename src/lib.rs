@@ -0,0 +1,8 @@
+pub mod animation;
+pub mod collision;
+pub mod entities;
+pub mod helpers;
+pub mod levels;
+pub mod motd;
+pub mod mover;
+pub mod player;
@@ -1,22 +1,33 @@
-mod animation;
-mod entities;
-mod helpers;
-mod levels;
-mod motd;
-mod player;
-
-use crate::motd::MotdPlugin;
-use animation::{AnimationBundle, AnimationLoadingStates, SpriteAnimationPlugin};
-use bevy::prelude::*;
+use bevy::{math::Vec3Swizzles, prelude::*};
 use bevy_asset_loader::prelude::*;
 use bevy_ecs_tilemap::TilemapPlugin;
-use entities::archer::ArcherBlue;
-use levels::{
-    coordinator::{LevelCoordniatorPlugin, LevelLoadingStates},
-    level1::Level1Asset,
-    Level, LevelBundle,
+use chapa_chapa_wizard::{
+    animation::{AnimationBundle, AnimationLoadingStates, SpriteAnimationPlugin},
+    collision::Collider,
+    entities::{
+        archer::{
+            archer_blue_prefab, archer_red_prefab, ArcherBlue, ArcherRed, ARCHER_FOOT_OFFSET,
+        },
+        building::{castle_prefab, goblin_house_prefab, house_prefab, tower_prefab, Buildings},
+        goblin::{goblin_blue_prefab, goblin_red_prefab, TorchGoblinBlue, TorchGoblinRed},
+    },
+    helpers::{
+        self,
+        coordinate_utils::TileLayout,
+        tiled::{LayerParallax, RemoveMap},
+        y_sort::{YSort, SORTED_Z},
+    },
+    levels::{
+        coordinator::{LevelCoordniatorPlugin, LevelLoadingStates},
+        objects::PrefabAppExt,
+        registry::LevelManifestAsset,
+        transition::LoadLevel,
+        Level,
+    },
+    motd::MotdPlugin,
+    mover::{KinematicMover, KinematicMoverPlugin},
+    player::{Player, PlayerBundle, PlayerPlugin, PLAYER_SPEED},
 };
-use player::{PlayerBundle, PlayerPlugin};
 
 fn main() {
     App::new()
@@ -33,46 +44,93 @@ fn main() {
         )
         .add_plugins(MotdPlugin)
         .add_plugins(TilemapPlugin)
+        .add_plugins(KinematicMoverPlugin)
         .add_plugins(PlayerPlugin)
         .add_plugins(LevelCoordniatorPlugin)
-        .add_collection_to_loading_state::<_, Level1Asset>(LevelLoadingStates::Loading)
+        .add_collection_to_loading_state::<_, LevelManifestAsset>(LevelLoadingStates::Loading)
         .add_plugins(SpriteAnimationPlugin)
         .add_collection_to_loading_state::<_, ArcherBlue>(AnimationLoadingStates::LoadingSprites)
+        .add_collection_to_loading_state::<_, ArcherRed>(AnimationLoadingStates::LoadingSprites)
+        .add_collection_to_loading_state::<_, TorchGoblinBlue>(
+            AnimationLoadingStates::LoadingSprites,
+        )
+        .add_collection_to_loading_state::<_, TorchGoblinRed>(
+            AnimationLoadingStates::LoadingSprites,
+        )
+        .add_collection_to_loading_state::<_, Buildings>(AnimationLoadingStates::LoadingSprites)
+        .register_prefab("archer_blue", archer_blue_prefab)
+        .register_prefab("archer_red", archer_red_prefab)
+        .register_prefab("goblin_blue", goblin_blue_prefab)
+        .register_prefab("goblin_red", goblin_red_prefab)
+        .register_prefab("house", house_prefab)
+        .register_prefab("tower", tower_prefab)
+        .register_prefab("castle", castle_prefab)
+        .register_prefab("goblin_house", goblin_house_prefab)
         .add_plugins(helpers::tiled::TiledMapPlugin)
-        .add_systems(OnEnter(AnimationLoadingStates::Ready), animations)
+        .add_systems(
+            Update,
+            spawn_player.run_if(
+                in_state(AnimationLoadingStates::Ready)
+                    .and_then(in_state(LevelLoadingStates::Ready))
+                    .and_then(not(any_with_component::<Player>())),
+            ),
+        )
         .add_systems(OnEnter(LevelLoadingStates::Ready), level)
         .run();
 }
 
-fn animations(
+fn spawn_player(
     mut commands: Commands,
     archer_blue_res: Res<ArcherBlue>,
     animation_bundle_assets: Res<Assets<AnimationBundle>>,
+    // The level being left keeps its layers until the frame after it's marked for removal
+    level: Query<(Entity, &Level), Without<RemoveMap>>,
+    tilemap: Query<(&TileLayout, &Transform), (Without<Camera>, Without<LayerParallax>)>,
+    mut camera: Query<&mut Transform, With<Camera>>,
+    mut reported_level: Local<Option<Entity>>,
 ) {
+    let Ok((level_entity, level)) = level.get_single() else {
+        return;
+    };
+    // Layers show up once the map asset is processed
+    let Some((layout, map_transform)) = tilemap.iter().next() else {
+        return;
+    };
+    let Some((x, y)) = level.spawn_tile() else {
+        // Runs every frame until the player spawns, report each level once
+        if reported_level.replace(level_entity) != Some(level_entity) {
+            error!("Level has no walkable tile to spawn the player on");
+        }
+        return;
+    };
+
+    let spawn = layout
+        .at(map_transform.translation.xy())
+        .tile_to_world(x, y);
+    for mut camera_transform in camera.iter_mut() {
+        camera_transform.translation = spawn.extend(camera_transform.translation.z);
+    }
+
     commands.spawn(PlayerBundle {
         sprite: SpriteSheetBundle {
             texture_atlas: archer_blue_res.texture_atlas.clone(),
-            transform: Transform::from_xyz(64. * -5., 64. * 2., 100.),
+            transform: Transform::from_translation(spawn.extend(SORTED_Z)),
             ..default()
         },
         animations: animation_bundle_assets
             .get(&archer_blue_res.animations)
             .unwrap()
             .clone(),
+        // Archer feet, the rest of the 192px sprite may overlap obstacles
+        collider: Collider::aabb(Vec2::new(20., 12.))
+            .with_offset(Vec2::new(0., ARCHER_FOOT_OFFSET)),
+        y_sort: YSort::new(ARCHER_FOOT_OFFSET),
+        mover: KinematicMover::new(PLAYER_SPEED),
         ..default()
     });
 }
 
-fn level(mut commands: Commands, level1: Res<Level1Asset>) {
+fn level(mut commands: Commands, mut load_level: EventWriter<LoadLevel>) {
     commands.spawn(Camera2dBundle::default());
-
-    commands.spawn(LevelBundle {
-        tilemap: helpers::tiled::TiledMapBundle {
-            tiled_map: level1.map.clone(),
-            ..default()
-        },
-        level: Level {
-            cfg: level1.config.clone(),
-        },
-    });
+    load_level.send(LoadLevel(0));
 }
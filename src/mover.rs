@@ -0,0 +1,158 @@
+use bevy::{math::Vec3Swizzles, prelude::*};
+
+use crate::{
+    collision::{Collider, TileCollisionGrid},
+    helpers::{coordinate_utils::TileLayout, tiled::LayerParallax},
+    levels::{elevation::Floor, Level},
+};
+
+/// Moves every [`KinematicMover`] by its [`MoveIntent`], stopping it at non-walkable tiles.
+pub struct KinematicMoverPlugin;
+
+impl Plugin for KinematicMoverPlugin {
+    fn build(&self, app: &mut App) {
+        app.configure_sets(
+            Update,
+            (
+                KinematicSet::Intent,
+                KinematicSet::Move,
+                KinematicSet::Resolve,
+            )
+                .chain(),
+        )
+        .add_systems(Update, move_kinematic.in_set(KinematicSet::Move));
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum KinematicSet {
+    /// Player input and AI writing [`MoveIntent`]
+    Intent,
+    Move,
+    /// Fixes positions after moving, such as snapping back onto walkable tiles
+    Resolve,
+}
+
+/// Where the entity wants to go, written by whatever controls it. The length is the fraction of
+/// the max speed to reach, up to 1.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default, Deref, DerefMut)]
+pub struct MoveIntent(pub Vec2);
+
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct KinematicMover {
+    pub velocity: Vec2,
+    /// Velocity change per second, infinite to reach the intended velocity at once
+    pub acceleration: f32,
+    pub max_speed: f32,
+    /// Whether obstacles stopped the last move
+    pub blocked: bool,
+}
+
+impl Default for KinematicMover {
+    fn default() -> Self {
+        Self::new(0.)
+    }
+}
+
+impl KinematicMover {
+    pub fn new(max_speed: f32) -> Self {
+        Self {
+            velocity: Vec2::ZERO,
+            acceleration: f32::INFINITY,
+            max_speed,
+            blocked: false,
+        }
+    }
+
+    pub fn with_acceleration(mut self, acceleration: f32) -> Self {
+        self.acceleration = acceleration;
+        self
+    }
+
+    /// Velocity after accelerating towards `intent` for `delta_seconds`.
+    pub fn steer(&mut self, intent: Vec2, delta_seconds: f32) -> Vec2 {
+        let target = intent.clamp_length_max(1.) * self.max_speed;
+        let change = (target - self.velocity).clamp_length_max(self.acceleration * delta_seconds);
+        self.velocity += change;
+        self.velocity
+    }
+}
+
+fn move_kinematic(
+    time: Res<Time>,
+    level: Query<&Level>,
+    tilemap: Query<(&TileLayout, &Transform), (Without<KinematicMover>, Without<LayerParallax>)>,
+    mut movers: Query<(
+        &mut Transform,
+        &mut KinematicMover,
+        &MoveIntent,
+        Option<&Collider>,
+        Option<&Floor>,
+    )>,
+) {
+    let delta_seconds = time.delta_seconds();
+    if delta_seconds <= 0. {
+        return;
+    }
+    let level = level.get_single().ok();
+    // Every layer shares the map grid, so the first one is enough
+    let tiles = level
+        .zip(tilemap.iter().next())
+        .map(|(l, (layout, map_transform))| {
+            TileCollisionGrid::from_map(
+                l.walkable_tiles.grid(),
+                layout.at(map_transform.translation.xy()),
+            )
+        });
+
+    for (mut transform, mut mover, intent, collider, floor) in movers.iter_mut() {
+        let change = mover.steer(**intent, delta_seconds) * delta_seconds;
+        if change == Vec2::ZERO {
+            mover.blocked = false;
+            continue;
+        }
+
+        // Entities without a collider move as a point
+        let collider = collider.copied().unwrap_or_default();
+        let position = transform.translation.xy();
+        let resolved = match (tiles, level.and_then(|l| l.elevation.as_ref()), floor) {
+            (Some(tiles), Some(elevation), Some(floor)) => tiles
+                .on_floor(elevation, **floor, position + collider.offset)
+                .resolve_movement(&collider, position, change),
+            (Some(tiles), _, _) => tiles.resolve_movement(&collider, position, change),
+            (None, _, _) => position + change,
+        };
+
+        // Sliding along a wall keeps only the part of the velocity that moved the entity
+        mover.velocity = (resolved - position) / delta_seconds;
+        mover.blocked = resolved == position;
+        if !mover.blocked {
+            transform.translation = resolved.extend(transform.translation.z);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use super::KinematicMover;
+
+    #[test]
+    fn test_steer_caps_speed() {
+        let mut mover = KinematicMover::new(10.);
+        assert_eq!(Vec2::new(10., 0.), mover.steer(Vec2::new(5., 0.), 0.1));
+        assert_eq!(Vec2::new(0., -5.), mover.steer(Vec2::new(0., -0.5), 0.1));
+        assert_eq!(Vec2::ZERO, mover.steer(Vec2::ZERO, 0.1));
+    }
+
+    #[test]
+    fn test_steer_accelerates_towards_intent() {
+        let mut mover = KinematicMover::new(10.).with_acceleration(20.);
+        assert_eq!(Vec2::new(2., 0.), mover.steer(Vec2::X, 0.1));
+        assert_eq!(Vec2::new(4., 0.), mover.steer(Vec2::X, 0.1));
+        assert_eq!(Vec2::new(10., 0.), mover.steer(Vec2::X, 1.));
+        // Braking too
+        assert_eq!(Vec2::new(8., 0.), mover.steer(Vec2::ZERO, 0.1));
+    }
+}
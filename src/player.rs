@@ -1,14 +1,17 @@
 use crate::{
     animation::{AnimationBundle, AnimationTimer, Animations, CurrentAnimation},
-    helpers::coordinate_utils::CoordinateOps,
-    levels::{Level, LevelConfig},
+    collision::Collider,
+    helpers::{coordinate_utils::TileLayout, tiled::LayerParallax, y_sort::YSort},
+    levels::{
+        elevation::Floor,
+        painter::painting,
+        pathfinding::{CachedPathfinder, GridKey},
+        Level,
+    },
+    mover::{KinematicMover, KinematicSet, MoveIntent},
 };
-use bevy::{input::mouse::MouseWheel, math::Vec3Swizzles, prelude::*};
-use bevy_ecs_tilemap::{
-    map::{TilemapGridSize, TilemapSize, TilemapType},
-    tiles::TilePos,
-};
-use std::f32::consts::PI;
+use bevy::{input::mouse::MouseWheel, math::Vec3Swizzles, prelude::*, window::PrimaryWindow};
+use std::{collections::VecDeque, f32::consts::PI};
 
 #[derive(Component)]
 pub struct Health {
@@ -45,8 +48,62 @@ impl Default for Health {
 #[derive(Default, Component)]
 pub struct Player;
 
+/// World positions of the tile centers left to walk through, nearest first.
+#[derive(Default, Component, Debug)]
+pub struct TilePath {
+    waypoints: VecDeque<Vec2>,
+}
+
+impl TilePath {
+    pub fn set(&mut self, waypoints: impl IntoIterator<Item = Vec2>) {
+        self.waypoints = waypoints.into_iter().collect();
+    }
+
+    pub fn clear(&mut self) {
+        self.waypoints.clear();
+    }
+
+    pub fn waypoints(&self) -> impl Iterator<Item = &Vec2> {
+        self.waypoints.iter()
+    }
+
+    /// [`MoveIntent`] towards the next waypoint from `position`, dropping the waypoints reached.
+    /// Slows down to land on waypoints and to brake in time for the last one, which is only
+    /// reached once the current velocity can stop within `delta_seconds`.
+    pub fn intent(&mut self, position: Vec2, mover: &KinematicMover, delta_seconds: f32) -> Vec2 {
+        let can_stop = mover.velocity.length() <= mover.acceleration * delta_seconds;
+        while self.waypoints.front().is_some_and(|waypoint| {
+            waypoint.distance(position) <= WAYPOINT_REACHED
+                && (self.waypoints.len() > 1 || can_stop)
+        }) {
+            self.waypoints.pop_front();
+        }
+        let Some(&waypoint) = self.waypoints.front() else {
+            return Vec2::ZERO;
+        };
+        let to_waypoint = waypoint - position;
+        let distance = to_waypoint.length();
+        if mover.max_speed <= 0. || distance == 0. {
+            return Vec2::ZERO;
+        }
+
+        let mut speed = mover.max_speed.min(distance / delta_seconds);
+        if self.waypoints.len() == 1 && mover.acceleration.is_finite() {
+            // Fastest speed still stopping on the waypoint, braking once per frame
+            let half_step = mover.acceleration * delta_seconds / 2.;
+            speed = speed.min(
+                (half_step * half_step + 2. * mover.acceleration * distance).sqrt() - half_step,
+            );
+        }
+        to_waypoint / distance * speed / mover.max_speed
+    }
+}
+
+/// Distance to a waypoint at which it counts as reached.
+const WAYPOINT_REACHED: f32 = 0.5;
 const IDLE: &str = "idle";
 const RUN: &str = "run";
+pub const PLAYER_SPEED: f32 = 500.;
 
 #[derive(Default, Bundle)]
 pub struct PlayerBundle {
@@ -55,18 +112,106 @@ pub struct PlayerBundle {
     pub sprite: SpriteSheetBundle,
     pub animations: AnimationBundle,
     pub animation_timer: AnimationTimer,
+    pub path: TilePath,
+    pub mover: KinematicMover,
+    pub intent: MoveIntent,
+    pub collider: Collider,
+    pub floor: Floor,
+    pub y_sort: YSort,
 }
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, movement);
+        // Clicks paint tiles in the walkability painter, and the player stays put meanwhile
+        app.add_systems(
+            Update,
+            (
+                (click_to_move, steer_player).chain().run_if(not(painting)),
+                stop_player.run_if(painting),
+            )
+                .in_set(KinematicSet::Intent),
+        )
+        .add_systems(
+            Update,
+            (follow_player, zoom_camera).after(KinematicSet::Move),
+        );
     }
 }
 
-fn movement(
-    mut mouse_input: EventReader<MouseWheel>,
+fn click_to_move(
+    mouse_buttons: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform)>,
+    mut player: Query<(&Transform, &mut TilePath, Option<&Floor>), With<Player>>,
+    level: Query<&Level>,
+    tilemap: Query<
+        (&TileLayout, &Transform),
+        (Without<Player>, Without<Camera>, Without<LayerParallax>),
+    >,
+    mut pathfinder: ResMut<CachedPathfinder>,
+) {
+    if !mouse_buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let Some(cursor) = windows.get_single().ok().and_then(|w| w.cursor_position()) else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        return;
+    };
+    let Some(target) = camera.viewport_to_world_2d(camera_transform, cursor) else {
+        return;
+    };
+    let Some(level) = level.iter().next() else {
+        return;
+    };
+    let Some((layout, map_transform)) = tilemap.iter().next() else {
+        return;
+    };
+    let layout = layout.at(map_transform.translation.xy());
+
+    let Some(to) = layout.tile_at(target) else {
+        debug!("Clicked outside of the map {:?}", target);
+        return;
+    };
+
+    for (player_transform, mut path, floor) in player.iter_mut() {
+        let player_pos = player_transform.translation.xy();
+        let Some(from) = layout.tile_at(player_pos) else {
+            continue;
+        };
+
+        // Floors are changed through stairs only, like when walking
+        let key = GridKey {
+            generation: level.generation(),
+            floor: level.elevation.as_ref().and(floor).map(|floor| **floor),
+        };
+        match pathfinder.find_path(
+            key,
+            level.walkable_tiles.grid(),
+            level.elevation.as_ref(),
+            from,
+            to,
+        ) {
+            Some(tiles) => path.set(
+                tiles
+                    .into_iter()
+                    .skip(1)
+                    .map(|(x, y)| layout.tile_to_world(x, y)),
+            ),
+            None => {
+                info!("No path from {:?} to {:?}", from, to);
+                path.clear();
+            }
+        }
+    }
+}
+
+/// Keys win over click-to-move, and cancel the path being walked.
+fn steer_player(
     keyboard_input: Res<Input<KeyCode>>,
     time: Res<Time>,
     mut player: Query<
@@ -76,106 +221,85 @@ fn movement(
             &mut AnimationTimer,
             &mut TextureAtlasSprite,
             &mut Transform,
+            &mut TilePath,
+            &mut MoveIntent,
+            &KinematicMover,
         ),
-        (With<Player>, Without<Camera>),
-    >,
-    level: Query<&Level>,
-    tilemap: Query<
-        (&TilemapGridSize, &TilemapType, &TilemapSize, &Transform),
-        (Without<Player>, Without<Camera>),
+        With<Player>,
     >,
-    level_config_assets: Res<Assets<LevelConfig>>,
-    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
 ) {
-    // Camera
-    for (mut camera_transform, mut ortho) in camera.iter_mut() {
-        for (mut current_animation, animations, mut timer, mut sprite, mut player_transform) in
-            player.iter_mut()
-        {
-            zoom_handler(&mut mouse_input, &mut ortho);
-
-            let z = player_transform.translation.z;
-            let direction = direction_from(&keyboard_input);
-
-            if direction.x > 0. {
-                player_transform.rotation = Quat::from_rotation_y(0.);
-            } else if direction.x < 0. {
-                player_transform.rotation = Quat::from_rotation_y(PI);
-            }
-
-            // idle / run
-            if direction.length() == 0. {
-                current_animation.change(&animations.get(IDLE), &mut sprite, &mut timer);
-            } else {
-                current_animation.change(&animations.get(RUN), &mut sprite, &mut timer);
-            }
-
-            let change = time.delta_seconds() * direction * 500.;
-            let mut changed_pos = player_transform.translation + change;
-
-            if is_hitting_obstacle(&changed_pos, &level, &tilemap, &level_config_assets) {
-                changed_pos = player_transform.translation;
-            }
-
-            if change != Vec3::ZERO {
-                player_transform.translation = changed_pos;
-                // Important! We need to restore the Z values when moving the camera around.
-                // Bevy has a specific camera setup and this can mess with how our layers are shown.
-                player_transform.translation.z = z;
+    for (
+        mut current_animation,
+        animations,
+        mut timer,
+        mut sprite,
+        mut player_transform,
+        mut path,
+        mut intent,
+        mover,
+    ) in player.iter_mut()
+    {
+        let direction = direction_from(&keyboard_input).xy();
+
+        **intent = if direction != Vec2::ZERO {
+            path.clear();
+            direction
+        } else {
+            path.intent(
+                player_transform.translation.xy(),
+                mover,
+                time.delta_seconds(),
+            )
+        };
+
+        if intent.x > 0. {
+            player_transform.rotation = Quat::from_rotation_y(0.);
+        } else if intent.x < 0. {
+            player_transform.rotation = Quat::from_rotation_y(PI);
+        }
 
-                // For now just follow eagerly
-                camera_transform.translation = player_transform.translation;
-            }
+        // idle / run
+        if **intent == Vec2::ZERO {
+            current_animation.change(&animations.get(IDLE), &mut sprite, &mut timer);
+        } else {
+            current_animation.change(&animations.get(RUN), &mut sprite, &mut timer);
         }
     }
 }
 
-// TODO: Move in generic entity controller
-fn is_hitting_obstacle(
-    entity_translation: &Vec3,
-    level: &Query<&Level>,
-    tilemap: &Query<
-        (&TilemapGridSize, &TilemapType, &TilemapSize, &Transform),
-        (Without<Player>, Without<Camera>),
-    >,
-    level_config_assets: &Res<Assets<LevelConfig>>,
-) -> bool {
-    let mut is_hit = false;
-
-    level.for_each(|l| {
-        tilemap.for_each(|(grid_size, map_type, map_size, map_transform)| {
-            if is_hit {
-                return;
-            }
-
-            let cfg = level_config_assets
-                .get(&l.cfg)
-                .expect("LevelConfig not found or unexpectedly unloaded!");
-            let entity_world_pos = entity_translation.xy();
-            let coordinate_zero =
-                map_transform.translation.xy().tiled_top_left(map_size, grid_size);
-            let entity_position = entity_world_pos.relative_to(&coordinate_zero).abs();
-            let pos = TilePos::from_world_pos(&entity_position, map_size, grid_size, map_type);
-
-            if pos.is_none() {
-                warn!(
-                    "Can't get tile for position {:?}. Orig: {:?} {:?}",
-                    entity_position, coordinate_zero, entity_world_pos
-                );
-                return;
-            }
-            let pos = pos.unwrap();
+fn stop_player(mut player: Query<(&mut MoveIntent, &mut TilePath), With<Player>>) {
+    for (mut intent, mut path) in player.iter_mut() {
+        **intent = Vec2::ZERO;
+        path.clear();
+    }
+}
 
-            if !cfg
-                .walkable_tiles
-                .is_walkable_local(pos.x as usize, pos.y as usize)
-            {
-                is_hit = true;
-            }
-        })
-    });
+fn follow_player(
+    mut player: Query<(&Transform, &KinematicMover, &mut TilePath), With<Player>>,
+    mut camera: Query<&mut Transform, (With<Camera>, Without<Player>)>,
+) {
+    for (player_transform, mover, mut path) in player.iter_mut() {
+        if mover.blocked {
+            // Stuck against a wall, the path can't be followed anymore
+            path.clear();
+        }
+        if mover.velocity == Vec2::ZERO {
+            continue;
+        }
+        // For now just follow eagerly
+        for mut camera_transform in camera.iter_mut() {
+            camera_transform.translation = player_transform.translation;
+        }
+    }
+}
 
-    is_hit
+fn zoom_camera(
+    mut mouse_input: EventReader<MouseWheel>,
+    mut camera: Query<&mut OrthographicProjection, With<Camera>>,
+) {
+    for mut ortho in camera.iter_mut() {
+        zoom_handler(&mut mouse_input, &mut ortho);
+    }
 }
 
 fn direction_from(keyboard_input: &Res<'_, Input<KeyCode>>) -> Vec3 {
@@ -220,3 +344,34 @@ fn zoom_handler(
         ortho.scale = 0.5;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use crate::mover::KinematicMover;
+
+    use super::TilePath;
+
+    #[test]
+    fn test_paths_stop_on_their_last_waypoint() {
+        let last = Vec2::new(64., 640.);
+        for mover in [
+            KinematicMover::new(500.),
+            KinematicMover::new(500.).with_acceleration(2000.),
+            KinematicMover::new(500.).with_acceleration(800.),
+        ] {
+            let mut mover = mover;
+            let mut path = TilePath::default();
+            path.set([Vec2::new(64., 0.), last]);
+            let mut position = Vec2::ZERO;
+            for _ in 0..600 {
+                let intent = path.intent(position, &mover, 1. / 60.);
+                position += mover.steer(intent, 1. / 60.) / 60.;
+            }
+            assert_eq!(0, path.waypoints().count());
+            assert_eq!(Vec2::ZERO, mover.velocity);
+            assert!(position.distance(last) <= 0.5, "{position}");
+        }
+    }
+}